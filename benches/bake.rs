@@ -0,0 +1,76 @@
+#[macro_use]
+extern crate criterion;
+extern crate gltf_unlit_generator;
+extern crate image;
+
+use criterion::{BatchSize, BenchmarkId, Criterion};
+use gltf_unlit_generator::{apply_emissive, apply_occlusion, apply_occlusion_linear, LinearImage};
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+const SIZES: [u32; 3] = [1024, 2048, 4096];
+
+/// A base color map with every channel varying, so no texel is a trivial case.
+fn base_map(size: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| Rgba([x as u8, y as u8, (x ^ y) as u8, 255]))
+}
+
+fn rgb_map(size: u32) -> RgbImage {
+    RgbImage::from_fn(size, size, |x, y| Rgb([(x + y) as u8, x as u8, y as u8]))
+}
+
+fn occlusion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply_occlusion");
+    for &size in &SIZES {
+        let base = base_map(size);
+        let occlusion_map = rgb_map(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter_batched_ref(|| base.clone(), |img| apply_occlusion(img, &occlusion_map, 1.0, 1.0, 0.0), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn occlusion_half_size(c: &mut Criterion) {
+    // A smaller occlusion map is sampled bilinearly instead of one texel at a time
+    let mut group = c.benchmark_group("apply_occlusion_half_size");
+    for &size in &SIZES {
+        let base = base_map(size);
+        let occlusion_map = rgb_map(size / 2);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter_batched_ref(|| base.clone(), |img| apply_occlusion(img, &occlusion_map, 1.0, 1.0, 0.0), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn occlusion_linear(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply_occlusion_linear");
+    for &size in &SIZES {
+        let base = LinearImage::from_pixel(size, size, Rgba([0.5, 0.25, 0.125, 1.0]));
+        let occlusion_map = rgb_map(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter_batched_ref(|| base.clone(), |img| apply_occlusion_linear(img, &occlusion_map, 1.0, 1.0, 0.0), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn emissive(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply_emissive");
+    for &size in &SIZES {
+        let base = base_map(size);
+        let emissive_map = rgb_map(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter_batched_ref(|| base.clone(), |img| apply_emissive(img, &emissive_map, [1.0, 0.5, 0.25]), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    // 4K images take long enough that the default 100 samples would take minutes
+    config = Criterion::default().sample_size(10);
+    targets = occlusion, occlusion_half_size, occlusion_linear, emissive
+}
+criterion_main!(benches);
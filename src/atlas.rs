@@ -0,0 +1,116 @@
+//! Packs unlit textures into a single power of two atlas.
+
+use image::RgbaImage;
+use image::imageops;
+
+use UnlitError;
+
+/// The region of an atlas holding one packed texture, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl AtlasRect {
+    /// The rect as `[u_min, v_min, u_max, v_max]` texture coordinates of an atlas
+    /// `size` pixels wide, with v increasing downwards like glTF.
+    pub fn uv_bounds(&self, size: u32) -> [f32; 4] {
+        let size = size as f32;
+        [
+            self.x as f32 / size,
+            self.y as f32 / size,
+            (self.x + self.width) as f32 / size,
+            (self.y + self.height) as f32 / size
+        ]
+    }
+}
+
+/// A square atlas image and the rect of each packed texture, in input order.
+#[derive(Clone, Debug)]
+pub struct Atlas {
+    pub image: RgbaImage,
+    pub rects: Vec<AtlasRect>
+}
+
+/// Packs `images` into the smallest square power of two atlas, no larger than
+/// `max_size`, that a shelf packer can fit them in.
+pub fn pack_atlas(images: &[&RgbaImage], max_size: u32) -> Result<Atlas, UnlitError> {
+    let sizes = images.iter().map(|img| img.dimensions()).collect::<Vec<_>>();
+    if let Some(&(width, height)) = sizes.iter().find(|&&(w, h)| w > max_size || h > max_size) {
+        return Err(UnlitError::AtlasTextureTooLarge { width, height, max_size });
+    }
+
+    // Start from the smallest size that could hold the total area and grow until
+    // every texture fits
+    let area = sizes.iter().map(|&(w, h)| w as u64 * h as u64).sum::<u64>();
+    let longest_side = sizes.iter().map(|&(w, h)| w.max(h)).max().unwrap_or(1);
+    let mut size = longest_side.max((area as f64).sqrt().ceil() as u32).next_power_of_two();
+    let rects = loop {
+        if size > max_size {
+            return Err(UnlitError::AtlasOverflow { max_size });
+        }
+        if let Some(rects) = pack_shelves(&sizes, size) {
+            break rects;
+        }
+        size *= 2;
+    };
+
+    let mut image = RgbaImage::new(size, size);
+    for (img, rect) in images.iter().zip(rects.iter()) {
+        imageops::replace(&mut image, *img, rect.x, rect.y);
+    }
+    Ok(Atlas { image, rects })
+}
+
+/// Places the textures tallest first in rows across a `size` square, returning
+/// `None` if they do not fit.
+fn pack_shelves(sizes: &[(u32, u32)], size: u32) -> Option<Vec<AtlasRect>> {
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| sizes[b].1.cmp(&sizes[a].1));
+
+    let mut rects = vec![AtlasRect { x: 0, y: 0, width: 0, height: 0 }; sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for index in order {
+        let (width, height) = sizes[index];
+        if x + width > size {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        if y + height > size {
+            return None;
+        }
+        rects[index] = AtlasRect { x, y, width, height };
+        x += width;
+        shelf_height = shelf_height.max(height);
+    }
+    Some(rects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_textures_without_overlap() {
+        let large = RgbaImage::new(64, 64);
+        let small = RgbaImage::new(32, 32);
+        let atlas = pack_atlas(&[&small, &large, &small], 1024).unwrap();
+        assert_eq!(atlas.image.dimensions(), (128, 128));
+        assert_eq!(atlas.rects[1], AtlasRect { x: 0, y: 0, width: 64, height: 64 });
+        assert_eq!(atlas.rects[0], AtlasRect { x: 64, y: 0, width: 32, height: 32 });
+        assert_eq!(atlas.rects[2], AtlasRect { x: 96, y: 0, width: 32, height: 32 });
+    }
+
+    #[test]
+    fn rejects_textures_larger_than_the_atlas() {
+        let img = RgbaImage::new(64, 8);
+        match pack_atlas(&[&img], 32) {
+            Err(UnlitError::AtlasTextureTooLarge { width: 64, height: 8, max_size: 32 }) => {},
+            result => panic!("unexpected result: {:?}", result.map(|atlas| atlas.rects))
+        }
+    }
+}
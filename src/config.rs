@@ -0,0 +1,238 @@
+//! Reads the `--config` file of the command line tool, a TOML file of
+//! `key = value` pairs named like the tool's long flags.
+//!
+//! The `toml` crate cannot be added as a dependency of this build, so only the
+//! flat subset of TOML that flags can be written in is parsed here: comments,
+//! bare or quoted keys, basic and literal strings, numbers, booleans, and arrays
+//! of those on one line. Everything else is rejected with the line it is on
+//! rather than misread: tables and inline tables, since no flag takes one,
+//! arrays and strings spanning several lines, and escapes other than `\n`,
+//! `\t`, `\"`, `\\` and `\uXXXX`, such as `\U0001F600`.
+
+use serde_json::{Number, Value as JsonValue};
+
+/// Parses the `key = value` pairs of a config file, in file order.
+pub fn parse_config(text: &str) -> Result<Vec<(String, JsonValue)>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut parser = Parser { rest: line.trim_start() };
+        let at = |message: &str| format!("Line {} of the config: {}", i + 1, message);
+        if parser.at_end() {
+            continue;
+        }
+        if parser.rest.starts_with('[') {
+            return Err(at("tables are not supported, every option goes at the top level."));
+        }
+        let key = parser.key().map_err(|e| at(&e))?;
+        if !parser.eat('=') {
+            return Err(at(&format!("expected = after {}.", key)));
+        }
+        let value = parser.value().map_err(|e| at(&e))?;
+        if !parser.at_end() {
+            return Err(at(&format!("unexpected {} after the value of {}.", parser.rest.trim(), key)));
+        }
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// Turns the value of a config key into the command line arguments that set it:
+/// a flag for true, nothing for false, and the flag and its value otherwise.
+/// Arrays of strings repeat the flag for each value, while arrays of numbers are
+/// one comma separated value, like `--lighten`.
+pub fn config_args(key: &str, value: &JsonValue) -> Result<Vec<String>, String> {
+    let flag = format!("--{}", key.replace('_', "-"));
+    let scalar = |value: &JsonValue| match *value {
+        JsonValue::String(ref s) => Ok(s.clone()),
+        JsonValue::Number(ref n) => Ok(n.to_string()),
+        _ => Err(format!("The {} config value must be a string, number, or boolean, or an array of them.", key))
+    };
+    match *value {
+        JsonValue::Bool(true) => Ok(vec![flag]),
+        JsonValue::Bool(false) => Ok(Vec::new()),
+        JsonValue::Array(ref values) if values.iter().all(|value| value.is_number()) => {
+            let values = values.iter().map(&scalar).collect::<Result<Vec<_>, _>>()?;
+            Ok(vec![flag, values.join(",")])
+        },
+        JsonValue::Array(ref values) => {
+            let mut args = Vec::new();
+            for value in values {
+                args.push(flag.clone());
+                args.push(scalar(value)?);
+            }
+            Ok(args)
+        },
+        ref value => Ok(vec![flag, scalar(value)?])
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str
+}
+
+impl<'a> Parser<'a> {
+    /// Skips whitespace, returning true if only a comment or nothing is left.
+    fn at_end(&mut self) -> bool {
+        self.rest = self.rest.trim_start();
+        self.rest.is_empty() || self.rest.starts_with('#')
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with(c) {
+            self.rest = &self.rest[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('"') || self.rest.starts_with('\'') {
+            return self.string();
+        }
+        let end = self.rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')).unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err(String::from("expected a key."));
+        }
+        let key = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        Ok(String::from(key))
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('{') {
+            return Err(String::from("inline tables are not supported, no option takes one."));
+        }
+        if self.rest.starts_with("\"\"\"") || self.rest.starts_with("'''") {
+            return Err(String::from("multi-line strings are not supported."));
+        }
+        if self.rest.starts_with('"') || self.rest.starts_with('\'') {
+            return self.string().map(JsonValue::String);
+        }
+        if self.eat('[') {
+            let mut values = Vec::new();
+            if self.eat(']') {
+                return Ok(JsonValue::Array(values));
+            }
+            loop {
+                values.push(self.value()?);
+                // A trailing comma is allowed before the closing bracket
+                if self.eat(',') {
+                    if self.eat(']') {
+                        return Ok(JsonValue::Array(values));
+                    }
+                } else if self.eat(']') {
+                    return Ok(JsonValue::Array(values));
+                } else {
+                    return Err(String::from("expected , or ] in an array. Arrays must be on one line."));
+                }
+            }
+        }
+        let end = self.rest.find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#').unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err(String::from("expected a value. Arrays must be on one line."));
+        }
+        let word = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        match word {
+            "true" => Ok(JsonValue::Bool(true)),
+            "false" => Ok(JsonValue::Bool(false)),
+            _ => {
+                // TOML allows underscores between digits
+                let number = word.replace('_', "");
+                let number = match number.parse::<i64>() {
+                    Ok(n) => Some(Number::from(n)),
+                    Err(_) => number.parse::<f64>().ok().and_then(Number::from_f64)
+                };
+                number.map(JsonValue::Number).ok_or_else(|| format!("{} is not a string, number, boolean, or array.", word))
+            }
+        }
+    }
+
+    /// Reads a basic `"..."` string with escapes, or a literal `'...'` string.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.rest.chars().next().unwrap();
+        let mut chars = self.rest[1..].char_indices();
+        let mut s = String::new();
+        while let Some((i, c)) = chars.next() {
+            if c == quote {
+                self.rest = &self.rest[1 + i + 1..];
+                return Ok(s);
+            }
+            if c != '\\' || quote == '\'' {
+                s.push(c);
+                continue;
+            }
+            match chars.next().map(|(_, c)| c) {
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('u') => {
+                    let hex = chars.by_ref().take(4).map(|(_, c)| c).collect::<String>();
+                    let escaped = u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32);
+                    s.push(escaped.ok_or_else(|| format!("\\u{} is not a valid escape.", hex))?);
+                },
+                Some(c) => return Err(format!("\\{} is not a supported escape.", c)),
+                None => break
+            }
+        }
+        Err(String::from("unterminated string."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        parse_config(text).unwrap_err()
+    }
+
+    #[test]
+    fn parses_flat_options() {
+        let entries = parse_config("# Options\nhdr = true\n\"metal-darken\" = 0.5\nlighten = [1, 2, 3,]\nout = 'C:\\out' # comment\nsuffix = \"_\\u00e9\"\n").unwrap();
+        assert_eq!(entries, vec![
+            (String::from("hdr"), JsonValue::Bool(true)),
+            (String::from("metal-darken"), JsonValue::from(0.5)),
+            (String::from("lighten"), JsonValue::from(vec![1, 2, 3])),
+            (String::from("out"), JsonValue::from("C:\\out")),
+            (String::from("suffix"), JsonValue::from("_\u{e9}"))
+        ]);
+    }
+
+    #[test]
+    fn rejects_tables() {
+        assert_eq!(error("hdr = true\n[output]\nformat = \"png\""), "Line 2 of the config: tables are not supported, every option goes at the top level.");
+    }
+
+    #[test]
+    fn rejects_inline_tables() {
+        assert_eq!(error("lighten = { r = 1 }"), "Line 1 of the config: inline tables are not supported, no option takes one.");
+    }
+
+    #[test]
+    fn rejects_multi_line_arrays() {
+        assert_eq!(error("include = [\n  \"a\",\n]"), "Line 1 of the config: expected a value. Arrays must be on one line.");
+        assert_eq!(error("include = [\"a\",\n  \"b\"]"), "Line 1 of the config: expected a value. Arrays must be on one line.");
+    }
+
+    #[test]
+    fn rejects_multi_line_strings() {
+        assert_eq!(error("suffix = \"\"\"_unlit\"\"\""), "Line 1 of the config: multi-line strings are not supported.");
+        assert_eq!(error("suffix = '''_unlit'''"), "Line 1 of the config: multi-line strings are not supported.");
+    }
+
+    #[test]
+    fn rejects_unsupported_escapes() {
+        // Eight digit escapes outside the Basic Multilingual Plane
+        assert_eq!(error("suffix = \"\\U0001F600\""), "Line 1 of the config: \\U is not a supported escape.");
+        // Surrogates are not characters
+        assert_eq!(error("suffix = \"\\uD83D\\uDE00\""), "Line 1 of the config: \\uD83D is not a valid escape.");
+        assert_eq!(error("suffix = \"\\u00\""), "Line 1 of the config: \\u00\" is not a valid escape.");
+        assert_eq!(error("suffix = \"\\r\""), "Line 1 of the config: \\r is not a supported escape.");
+    }
+}
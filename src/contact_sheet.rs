@@ -0,0 +1,146 @@
+//! Tiles downscaled unlit textures into one labeled grid image for review.
+//!
+//! Labels are drawn with a built-in 5x7 pixel font covering printable ASCII, so
+//! no font has to be installed or loaded. Other characters are drawn as `?`.
+
+use image::{FilterType, Rgba, RgbaImage};
+use image::imageops;
+
+/// Space between cells and around the sheet, in pixels.
+const PADDING: u32 = 4;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// A glyph and the column of space after it.
+const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+/// The height of a label under its thumbnail.
+const LABEL_HEIGHT: u32 = GLYPH_HEIGHT + 2 * 2;
+const SHEET_COLOR: [u8; 3] = [32, 32, 32];
+const LABEL_COLOR: [u8; 3] = [230, 230, 230];
+/// The squares of the checkerboard drawn behind transparent texels.
+const CHECKER_SIZE: u32 = 8;
+const CHECKER_COLORS: [u8; 2] = [96, 160];
+
+/// Columns of each glyph from ' ' to '~', left to right, with the top row in the
+/// lowest bit.
+const GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x56, 0x20, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01], [0x3E, 0x41, 0x49, 0x49, 0x7A],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x0C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00], [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08]
+];
+
+/// Scales `img` to fit in a `size` by `size` square, keeping its aspect ratio.
+/// Small textures are enlarged without smoothing so their texels stay visible.
+pub fn thumbnail(img: &RgbaImage, size: u32) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let longest_side = width.max(height).max(1);
+    let fit = |side: u32| ((side as u64 * size as u64 + longest_side as u64 / 2) / longest_side as u64).max(1) as u32;
+    let filter = if longest_side < size { FilterType::Nearest } else { FilterType::Triangle };
+    imageops::resize(img, fit(width), fit(height), filter)
+}
+
+/// Tiles `cells` into a grid of `cell_size` squares, each labeled with its name,
+/// in as many columns as rows. Textures are shrunk to fit their cell with
+/// [`thumbnail`] and drawn over a checkerboard, so transparency is visible.
+pub fn contact_sheet(cells: &[(&str, &RgbaImage)], cell_size: u32) -> RgbaImage {
+    let columns = (cells.len() as f64).sqrt().ceil().max(1.0) as u32;
+    let rows = (cells.len() as u32).div_ceil(columns);
+    let cell_height = cell_size + LABEL_HEIGHT;
+    let mut sheet = RgbaImage::from_pixel(
+        PADDING + columns * (cell_size + PADDING),
+        PADDING + rows.max(1) * (cell_height + PADDING),
+        Rgba([SHEET_COLOR[0], SHEET_COLOR[1], SHEET_COLOR[2], 255]));
+
+    for (i, &(name, img)) in cells.iter().enumerate() {
+        let x0 = PADDING + i as u32 % columns * (cell_size + PADDING);
+        let y0 = PADDING + i as u32 / columns * (cell_height + PADDING);
+        let thumb = if img.width() > cell_size || img.height() > cell_size { thumbnail(img, cell_size) } else { img.clone() };
+        // Centered in the square above the label
+        let x1 = x0 + (cell_size - thumb.width()) / 2;
+        let y1 = y0 + (cell_size - thumb.height()) / 2;
+        for (x, y, pixel) in thumb.enumerate_pixels() {
+            let checker = CHECKER_COLORS[((x / CHECKER_SIZE + y / CHECKER_SIZE) % 2) as usize] as u32;
+            let alpha = pixel.data[3] as u32;
+            let out = sheet.get_pixel_mut(x1 + x, y1 + y);
+            for channel in 0..3 {
+                out.data[channel] = ((pixel.data[channel] as u32 * alpha + checker * (255 - alpha) + 127) / 255) as u8;
+            }
+        }
+        draw_label(&mut sheet, x0, y0 + cell_size + (LABEL_HEIGHT - GLYPH_HEIGHT) / 2, cell_size, name);
+    }
+    sheet
+}
+
+/// Draws `text` left to right from `x`, `y`, cutting it short with `..` if it is
+/// wider than `max_width`.
+fn draw_label(sheet: &mut RgbaImage, x: u32, y: u32, max_width: u32, text: &str) {
+    let max_chars = (max_width / GLYPH_ADVANCE) as usize;
+    let mut chars = text.chars().collect::<Vec<_>>();
+    if chars.len() > max_chars {
+        chars.truncate(max_chars.saturating_sub(2));
+        chars.extend("..".chars());
+        chars.truncate(max_chars);
+    }
+    for (i, c) in chars.into_iter().enumerate() {
+        let glyph = match c {
+            ' ' ..= '~' => &GLYPHS[c as usize - ' ' as usize],
+            _ => &GLYPHS['?' as usize - ' ' as usize]
+        };
+        let gx = x + i as u32 * GLYPH_ADVANCE;
+        for (column, &bits) in glyph.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if bits >> row & 1 == 1 {
+                    sheet.put_pixel(gx + column as u32, y + row, Rgba([LABEL_COLOR[0], LABEL_COLOR[1], LABEL_COLOR[2], 255]));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cells_in_a_square_grid() {
+        let img = RgbaImage::from_pixel(256, 128, Rgba([255, 0, 0, 255]));
+        let sheet = contact_sheet(&[("a", &img), ("b", &img), ("c", &img)], 64);
+        // Two columns and two rows of 64 pixel squares with labels under them
+        assert_eq!(sheet.dimensions(), (PADDING + 2 * (64 + PADDING), PADDING + 2 * (64 + LABEL_HEIGHT + PADDING)));
+        // The 2:1 texture is 64x32 and centered vertically in its square
+        assert_eq!(sheet.get_pixel(PADDING, PADDING + 16).data, [255, 0, 0, 255]);
+        assert_eq!(sheet.get_pixel(PADDING, PADDING + 15).data, [SHEET_COLOR[0], SHEET_COLOR[1], SHEET_COLOR[2], 255]);
+    }
+
+    #[test]
+    fn cuts_labels_short_to_fit_their_cell() {
+        let img = RgbaImage::new(4, 4);
+        let sheet = contact_sheet(&[("Material.001", &img)], 32);
+        let y0 = PADDING + 32 + (LABEL_HEIGHT - GLYPH_HEIGHT) / 2;
+        let lit = |x0: u32, x1: u32| (x0..x1).any(|x| (y0..y0 + GLYPH_HEIGHT).any(|y| sheet.get_pixel(x, y).data[0] == LABEL_COLOR[0]));
+        // Five characters fit in a 32 pixel cell, which are drawn as Mat..
+        assert!(lit(PADDING, PADDING + GLYPH_WIDTH));
+        assert!(lit(PADDING + 4 * GLYPH_ADVANCE, PADDING + 5 * GLYPH_ADVANCE));
+        assert!(!lit(PADDING + 5 * GLYPH_ADVANCE, sheet.width()));
+    }
+}
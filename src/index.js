@@ -28,6 +28,10 @@ module.exports = async function generateUnlitTextures(gltfPath, outPath, options
     args.push("-l", options.lighten);
   }
 
+  if (options && options.force) {
+    args.push("--force");
+  }
+
   const gltf = await fs.readJSON(gltfPath);
 
   if (!gltf.materials) {
@@ -0,0 +1,185 @@
+//! Decodes KTX2 images, as used by KHR_texture_basisu, when the `ktx2` feature is
+//! enabled, and encodes uncompressed mip chains.
+//!
+//! Uncompressed 8-bit RGB and RGBA images and UASTC images, optionally
+//! supercompressed with Zstandard, are supported. Only the base level of the
+//! first layer and face is decoded.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use image::{DynamicImage, RgbaImage};
+
+use UnlitError;
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Returns true if `data` starts with the KTX2 file identifier.
+pub fn is_ktx2(data: &[u8]) -> bool {
+    data.starts_with(&KTX2_IDENTIFIER)
+}
+
+/// Returns true if `path` has a `.ktx2` extension, in any case.
+pub fn has_ktx2_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ktx2"))
+}
+
+#[cfg(not(feature = "ktx2"))]
+pub fn decode_ktx2(_data: &[u8]) -> Result<DynamicImage, UnlitError> {
+    Err(UnlitError::Ktx2Unsupported)
+}
+
+#[cfg(feature = "ktx2")]
+pub fn decode_ktx2(data: &[u8]) -> Result<DynamicImage, UnlitError> {
+    use std::borrow::Cow;
+    use std::io::Read;
+
+    use basis_universal::{DecodeFlags, LowLevelUastcTranscoder, SliceParametersUastc, TranscoderBlockFormat};
+    use image::{RgbImage, RgbaImage};
+    use ktx2::{ColorModel, Format, Reader, SupercompressionScheme};
+    use ruzstd::decoding::StreamingDecoder;
+
+    let reader = Reader::new(data).map_err(|e| UnlitError::Ktx2(e.to_string()))?;
+    let header = reader.header();
+    let (w, h) = (header.pixel_width, header.pixel_height.max(1));
+    let level = reader.levels().next().ok_or_else(|| UnlitError::Ktx2(String::from("The image has no levels.")))?;
+
+    let level_data = match header.supercompression_scheme {
+        None => Cow::Borrowed(level.data),
+        Some(SupercompressionScheme::Zstandard) => {
+            let mut decompressed = Vec::with_capacity(level.uncompressed_byte_length as usize);
+            StreamingDecoder::new(level.data)
+                .map_err(|e| UnlitError::Ktx2(e.to_string()))?
+                .read_to_end(&mut decompressed)?;
+            Cow::Owned(decompressed)
+        },
+        Some(scheme) => return Err(UnlitError::Ktx2(format!("{:?} supercompression is not supported.", scheme)))
+    };
+
+    let truncated = || UnlitError::Ktx2(String::from("The base level is shorter than the image."));
+    match header.format {
+        Some(Format::R8G8B8A8_UNORM) | Some(Format::R8G8B8A8_SRGB) => {
+            let pixels = level_data.get(..(w * h * 4) as usize).ok_or_else(truncated)?;
+            Ok(DynamicImage::ImageRgba8(RgbaImage::from_raw(w, h, pixels.to_vec()).unwrap()))
+        },
+        Some(Format::R8G8B8_UNORM) | Some(Format::R8G8B8_SRGB) => {
+            let pixels = level_data.get(..(w * h * 3) as usize).ok_or_else(truncated)?;
+            Ok(DynamicImage::ImageRgb8(RgbImage::from_raw(w, h, pixels.to_vec()).unwrap()))
+        },
+        None if reader.color_model() == Some(ColorModel::UASTC) => {
+            // UASTC stores 16 bytes per 4x4 block
+            let (blocks_x, blocks_y) = ((w + 3) / 4, (h + 3) / 4);
+            let blocks = level_data.get(..(blocks_x * blocks_y * 16) as usize).ok_or_else(truncated)?;
+            let params = SliceParametersUastc {
+                num_blocks_x: blocks_x,
+                num_blocks_y: blocks_y,
+                has_alpha: true,
+                original_width: w,
+                original_height: h
+            };
+            let pixels = LowLevelUastcTranscoder::new()
+                .transcode_slice(blocks, params, DecodeFlags::empty(), TranscoderBlockFormat::RGBA32)
+                .map_err(|e| UnlitError::Ktx2(format!("{:?}", e)))?;
+            RgbaImage::from_raw(w, h, pixels)
+                .map(DynamicImage::ImageRgba8)
+                .ok_or_else(|| UnlitError::Ktx2(String::from("The transcoded image is too short.")))
+        },
+        None if reader.color_model() == Some(ColorModel::ETC1S) => {
+            Err(UnlitError::Ktx2(String::from("ETC1S (BasisLZ) images are not supported, use UASTC.")))
+        },
+        format => Err(UnlitError::Ktx2(format!("The {:?} format is not supported.", format)))
+    }
+}
+
+/// VK_FORMAT_R8G8B8A8_SRGB
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+
+/// The size of the header and its index of the data format descriptor and other
+/// sections, which the level index follows.
+const HEADER_LENGTH: u32 = 80;
+
+/// A data format descriptor of a basic block with four samples.
+const DFD_LENGTH: u32 = 4 + 24 + 4 * 16;
+
+/// Writes `levels`, largest first, as the mip levels of an uncompressed sRGB
+/// RGBA8 KTX2 image. Each level must be half the size of the one before it,
+/// rounded down and at least 1.
+pub fn encode_ktx2<W: Write>(w: W, levels: &[RgbaImage]) -> io::Result<()> {
+    let (width, height) = levels[0].dimensions();
+    let levels = levels.iter().map(|level| &**level).collect::<Vec<_>>();
+    write_ktx2(w, width, height, 0, &levels)
+}
+
+/// Writes `layers` as the layers of an uncompressed sRGB RGBA8 KTX2 array
+/// texture with a single mip level. Every layer must be the same size.
+pub fn encode_ktx2_array<W: Write>(w: W, layers: &[&RgbaImage]) -> io::Result<()> {
+    let (width, height) = layers[0].dimensions();
+    let mut level = Vec::with_capacity(layers.iter().map(|layer| layer.len()).sum());
+    for layer in layers {
+        level.extend_from_slice(layer);
+    }
+    write_ktx2(w, width, height, layers.len() as u32, &[&level])
+}
+
+/// Writes a KTX2 image of `levels`, largest first, each holding every layer of
+/// that level. A `layer_count` of 0 is not an array texture.
+fn write_ktx2<W: Write>(mut w: W, width: u32, height: u32, layer_count: u32, levels: &[&[u8]]) -> io::Result<()> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&KTX2_IDENTIFIER);
+    // vkFormat, typeSize, pixelWidth, pixelHeight, pixelDepth, layerCount, faceCount,
+    // levelCount, supercompressionScheme
+    for &value in &[VK_FORMAT_R8G8B8A8_SRGB, 1, width, height, 0, layer_count, 1, levels.len() as u32, 0] {
+        push_u32(&mut data, value);
+    }
+    let dfd_offset = HEADER_LENGTH + 24 * levels.len() as u32;
+    // dfdByteOffset, dfdByteLength, kvdByteOffset, kvdByteLength, then the unused
+    // 64-bit sgdByteOffset and sgdByteLength
+    for &value in &[dfd_offset, DFD_LENGTH, 0, 0] {
+        push_u32(&mut data, value);
+    }
+    data.extend_from_slice(&[0; 16]);
+
+    // Levels are stored smallest first, but indexed largest first. Every offset is
+    // a multiple of 4, as all the sections before them are.
+    let mut offsets = vec![0u64; levels.len()];
+    let mut offset = (dfd_offset + DFD_LENGTH) as u64;
+    for (index, level) in levels.iter().enumerate().rev() {
+        offsets[index] = offset;
+        offset += level.len() as u64;
+    }
+    for (level, &offset) in levels.iter().zip(&offsets) {
+        // byteOffset, byteLength, uncompressedByteLength
+        for &value in &[offset, level.len() as u64, level.len() as u64] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    push_u32(&mut data, DFD_LENGTH);
+    // vendorId and descriptorType, then versionNumber 2 and descriptorBlockSize
+    push_u32(&mut data, 0);
+    push_u32(&mut data, 2 | (DFD_LENGTH - 4) << 16);
+    // KHR_DF_MODEL_RGBSDA, KHR_DF_PRIMARIES_BT709, KHR_DF_TRANSFER_SRGB, straight alpha
+    data.extend_from_slice(&[1, 1, 2, 0]);
+    // texelBlockDimension, then bytesPlane0 - 7
+    data.extend_from_slice(&[0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
+    // Red, green, blue, and alpha, which is linear in an sRGB image
+    for (index, &channel) in [0u32, 1, 2, 15 | 0x40].iter().enumerate() {
+        // bitOffset, bitLength - 1, and channelType
+        push_u32(&mut data, (index as u32 * 8) | 7 << 16 | channel << 24);
+        // samplePosition, sampleLower, and sampleUpper
+        for &value in &[0, 0, 255] {
+            push_u32(&mut data, value);
+        }
+    }
+
+    for level in levels.iter().rev() {
+        data.extend_from_slice(level);
+    }
+    w.write_all(&data)
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
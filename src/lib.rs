@@ -0,0 +1,3118 @@
+//! Bakes the base color, occlusion, and emissive maps of glTF materials into a
+//! single unlit texture.
+//!
+//! The command line tool is a thin wrapper around [`generate_unlit`], which can
+//! also be called directly from a build pipeline. Depending on the crate with
+//! `default-features = false` leaves out the `cli` feature and the dependencies
+//! only the tool needs.
+//!
+//! [`generate_all_from_bytes`] bakes a glTF and its textures entirely in memory.
+//! The `wasm` feature wraps it for JavaScript as `generate_unlit_bytes`, built
+//! with `--no-default-features --features wasm` for `wasm32-unknown-unknown`.
+
+extern crate base64;
+extern crate image;
+extern crate gltf;
+#[macro_use]
+extern crate log;
+extern crate png;
+extern crate rayon;
+extern crate serde_json;
+#[cfg(feature = "ktx2")]
+extern crate basis_universal;
+#[cfg(feature = "ktx2")]
+extern crate ktx2;
+#[cfg(feature = "ktx2")]
+extern crate ruzstd;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
+pub mod atlas;
+pub mod contact_sheet;
+pub mod ktx;
+pub mod png16;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use image::{ColorType, DynamicImage, GenericImage, FilterType, ImageBuffer, ImageDecoder, ImageError, ImageFormat, Pixel, Rgb, RgbImage, Rgba, RgbaImage};
+use image::imageops;
+use image::jpeg::{JPEGDecoder, JPEGEncoder};
+use image::png::{PNGDecoder, PNGEncoder};
+use rayon::prelude::*;
+use gltf::{Glb, Gltf, Material, Texture};
+use gltf::json::validation::{Error as ValidationError, Validate};
+use gltf::gltf::Unvalidated;
+use gltf::material::AlphaMode;
+use gltf::texture::WrappingMode;
+use gltf::image::Data;
+use serde_json::Value as JsonValue;
+
+/// Errors produced while generating an unlit texture.
+#[derive(Debug)]
+pub enum UnlitError {
+    /// None of the material's textures could be loaded, so there are no
+    /// dimensions to bake at.
+    MissingTexture,
+    /// An input map differs in size from the other maps. Use
+    /// `UnlitOptions::resize` to resample it instead.
+    DimensionMismatch { expected: (u32, u32), found: (u32, u32) },
+    /// Two maps sample different TEXCOORD_n sets.
+    TexCoordMismatch { map: &'static str, tex_coord: u32, reference: &'static str, reference_tex_coord: u32 },
+    /// Two maps have different KHR_texture_transforms. The occlusion map may have
+    /// its own transform since it is sampled separately.
+    TransformMismatch { map: &'static str, reference: &'static str },
+    /// A data URI that is malformed or not base64 encoded.
+    UnsupportedDataUri(String),
+    /// A buffer view image whose buffer is not loaded or is too short.
+    UnsupportedDataView(String),
+    /// A texture is larger than the atlas it should be packed into.
+    AtlasTextureTooLarge { width: u32, height: u32, max_size: u32 },
+    /// The textures do not all fit in an atlas of the maximum size.
+    AtlasOverflow { max_size: u32 },
+    /// An image is a GPU compressed texture container, such as KTX or DDS, which
+    /// cannot be decoded.
+    CompressedTexture(&'static str),
+    /// A generated texture does not have the dimensions or channels it was
+    /// planned with, or cannot be written in the chosen format.
+    InvalidOutput(String),
+    /// A KTX2 image was found but the `ktx2` feature is disabled.
+    Ktx2Unsupported,
+    /// A KTX2 image that is malformed or uses an encoding that cannot be decoded.
+    Ktx2(String),
+    /// A glTF or .glb passed as bytes that cannot be parsed.
+    InvalidGltf(String),
+    Io(io::Error),
+    ImageDecode(ImageError)
+}
+
+impl fmt::Display for UnlitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnlitError::MissingTexture => write!(f, "No input maps were provided."),
+            UnlitError::DimensionMismatch { found, .. } => write!(f, "Input map has inconsistent dimensions: {:?}", found),
+            UnlitError::TexCoordMismatch { map, tex_coord, reference, reference_tex_coord } => write!(f,
+                "The {} texture uses TEXCOORD_{} but the {} texture uses TEXCOORD_{}, so they cannot be combined.",
+                map, tex_coord, reference, reference_tex_coord),
+            UnlitError::TransformMismatch { map, reference } => write!(f,
+                "The {} texture has a different KHR_texture_transform than the {} texture and would be misaligned.",
+                map, reference),
+            UnlitError::UnsupportedDataUri(ref message) | UnlitError::UnsupportedDataView(ref message) => write!(f, "{}", message),
+            UnlitError::AtlasTextureTooLarge { width, height, max_size } => write!(f,
+                "A {}x{} texture does not fit in an atlas of at most {}x{}.", width, height, max_size, max_size),
+            UnlitError::AtlasOverflow { max_size } => write!(f,
+                "The textures do not fit in an atlas of at most {}x{}.", max_size, max_size),
+            UnlitError::CompressedTexture(format) => write!(f,
+                "{} is a GPU compressed texture format that cannot be decoded. Convert the texture to PNG or JPEG.", format),
+            UnlitError::InvalidOutput(ref message) => write!(f, "Invalid output texture: {}", message),
+            UnlitError::Ktx2Unsupported => write!(f, "KTX2 not supported; enable the ktx2 feature."),
+            UnlitError::Ktx2(ref message) => write!(f, "Unable to decode KTX2 image: {}", message),
+            UnlitError::InvalidGltf(ref message) => write!(f, "Invalid glTF: {}", message),
+            UnlitError::Io(ref e) => write!(f, "{}", e),
+            UnlitError::ImageDecode(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for UnlitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            UnlitError::Io(ref e) => Some(e),
+            UnlitError::ImageDecode(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for UnlitError {
+    fn from(e: io::Error) -> UnlitError {
+        UnlitError::Io(e)
+    }
+}
+
+impl From<ImageError> for UnlitError {
+    fn from(e: ImageError) -> UnlitError {
+        match e {
+            ImageError::IoError(e) => UnlitError::Io(e),
+            e => UnlitError::ImageDecode(e)
+        }
+    }
+}
+
+/// Options controlling how unlit textures are generated.
+#[derive(Clone, Debug)]
+pub struct UnlitOptions {
+    /// Values 0.0 - 1.0 added to the R, G, and B channels of the base color map.
+    /// In linear mode they are added in linear light.
+    pub lighten_factor: [f32; 3],
+    /// Replaces the base color factor of every material, including the diffuse
+    /// factor of KHR_materials_pbrSpecularGlossiness materials.
+    pub base_color_factor: Option<[f32; 4]>,
+    /// Multiplies the base color in linear light, after the base color factor and
+    /// before `gamma` and the lighten factor.
+    pub exposure: f32,
+    /// Raises the exposed linear base color to the power of `1.0 / gamma`, so values
+    /// above 1.0 brighten midtones without washing out highlights.
+    pub gamma: f32,
+    /// Scalar value 0.0 - 1.0 controlling how much smooth metallic texels are
+    /// darkened to approximate their lost specular reflection. 0.0 disables it.
+    pub metal_darken: f32,
+    /// Composite the maps in linear light instead of directly on the 8-bit sRGB values.
+    pub linear: bool,
+    /// Store the RGB channels of the 8-bit output as linear values instead of sRGB,
+    /// for engines that import unlit colors as linear data. This is independent of
+    /// `linear`, which only changes how the maps are composited: the output is
+    /// converted once whichever way it was composited.
+    pub output_linear: bool,
+    /// Resample maps of differing sizes up to the largest map with this filter.
+    /// When `None`, maps of differing sizes are an error.
+    pub resize: Option<ResizeFilter>,
+    /// Crop or pad maps of differing sizes to the output instead, keeping their
+    /// texels 1:1. Padding is transparent in the base color map and black in the
+    /// others. The occlusion map is still sampled at the output size. Ignored
+    /// when `resize` is set.
+    pub reconcile: Option<Reconcile>,
+    /// Size of the monocolor texture generated from the base color factor of a
+    /// material without any maps. When `None`, such materials are an error.
+    pub default_size: Option<u32>,
+    /// Multiply the RGB channels of the 8-bit output by its alpha. Opaque materials
+    /// ignore alpha and are left as is. It applies to the composited texture, so
+    /// occlusion and emissive are premultiplied consistently with the base color.
+    pub premultiply: bool,
+    /// Factor in (0.0, 1.0] the finished texture is downscaled by with a Lanczos
+    /// filter, after every map has been composited at full resolution.
+    pub scale: f32,
+    /// Minimum occlusion factor 0.0 - 1.0, so occlusion never darkens a texel below
+    /// this fraction of its base color whatever the occlusion strength.
+    pub ao_floor: f32,
+    /// Gamma the occlusion factor is raised to the reciprocal of before it is
+    /// multiplied in, like `gamma`. Above 1.0 softens shadows, below deepens them.
+    pub ao_gamma: f32,
+    /// The channel of the occlusion texture occlusion is read from. glTF stores it
+    /// in red.
+    pub occlusion_channel: OcclusionChannel,
+    /// Occlusion strength used instead of each material's occlusionTexture
+    /// strength, clamped to 0.0 - 1.0. It softens occlusion like glTF's strength,
+    /// from none at 0.0 to the full occlusion map at 1.0. Materials without an
+    /// occlusion texture are still not occluded.
+    pub occlusion_strength: Option<f32>,
+    /// A second occlusion map multiplied on top of every material's, such as
+    /// macro occlusion kept apart from a material's micro occlusion. It must be the
+    /// size of the output, and is read from the red channel at full strength.
+    pub extra_occlusion: Option<Arc<RgbImage>>,
+    /// Dither the linear bake when it is quantized to 8 bits, to hide banding.
+    /// The 8-bit sRGB bake is never quantized from higher precision, so this
+    /// only applies with `linear`.
+    pub dither: bool,
+    /// Resize the finished texture to power of two dimensions, rounding each side
+    /// this way, after `scale` is applied.
+    pub power_of_two: Option<PowerOfTwo>,
+    /// Proportionally downscale finished textures larger than this on either
+    /// side, after `scale` and `power_of_two`.
+    pub max_dimension: Option<u32>,
+    /// Warn about maps that look like they are in the wrong slot, such as an
+    /// occlusion map used as the emissive map.
+    pub sanity_check: bool,
+    /// Ignore every map and generate a solid texture of the base color factor at
+    /// `default_size`, without loading any textures.
+    pub solid: bool,
+    /// Flip the finished texture vertically, for engines with a bottom left UV
+    /// origin instead of glTF's top left.
+    pub flip_v: bool,
+    /// Composite the maps in bands of this many rows in parallel, for very large
+    /// textures. When `None`, each texture is composited on a single thread.
+    pub tile_height: Option<u32>
+}
+
+impl Default for UnlitOptions {
+    fn default() -> UnlitOptions {
+        UnlitOptions { lighten_factor: [0.0; 3], base_color_factor: None, exposure: 1.0, gamma: 1.0, metal_darken: 0.0, linear: false, output_linear: false, resize: None, reconcile: None, default_size: None, premultiply: false, scale: 1.0, ao_floor: 0.0, ao_gamma: 1.0, occlusion_channel: OcclusionChannel::Red, occlusion_strength: None, extra_occlusion: None, dither: false, power_of_two: None, max_dimension: None, sanity_check: false, solid: false, flip_v: false, tile_height: None }
+    }
+}
+
+impl UnlitOptions {
+    /// Starts building options from the defaults of the command line tool.
+    pub fn builder() -> UnlitOptionsBuilder {
+        UnlitOptionsBuilder::new()
+    }
+}
+
+/// Builds [`UnlitOptions`], with each method setting the field of the same name.
+///
+/// It starts from the defaults of the command line tool, which are those of
+/// `UnlitOptions::default()` except that materials without any maps get a 4x4
+/// texture of their base color factor instead of failing.
+#[derive(Clone, Debug)]
+pub struct UnlitOptionsBuilder {
+    opts: UnlitOptions
+}
+
+impl UnlitOptionsBuilder {
+    pub fn new() -> UnlitOptionsBuilder {
+        UnlitOptionsBuilder { opts: UnlitOptions { default_size: Some(4), ..UnlitOptions::default() } }
+    }
+
+    pub fn lighten_factor(mut self, lighten_factor: [f32; 3]) -> UnlitOptionsBuilder {
+        self.opts.lighten_factor = lighten_factor;
+        self
+    }
+
+    pub fn base_color_factor(mut self, base_color_factor: Option<[f32; 4]>) -> UnlitOptionsBuilder {
+        self.opts.base_color_factor = base_color_factor;
+        self
+    }
+
+    pub fn exposure(mut self, exposure: f32) -> UnlitOptionsBuilder {
+        self.opts.exposure = exposure;
+        self
+    }
+
+    pub fn gamma(mut self, gamma: f32) -> UnlitOptionsBuilder {
+        self.opts.gamma = gamma;
+        self
+    }
+
+    pub fn metal_darken(mut self, metal_darken: f32) -> UnlitOptionsBuilder {
+        self.opts.metal_darken = metal_darken;
+        self
+    }
+
+    pub fn linear(mut self, linear: bool) -> UnlitOptionsBuilder {
+        self.opts.linear = linear;
+        self
+    }
+
+    pub fn output_linear(mut self, output_linear: bool) -> UnlitOptionsBuilder {
+        self.opts.output_linear = output_linear;
+        self
+    }
+
+    pub fn resize(mut self, resize: Option<ResizeFilter>) -> UnlitOptionsBuilder {
+        self.opts.resize = resize;
+        self
+    }
+
+    pub fn reconcile(mut self, reconcile: Option<Reconcile>) -> UnlitOptionsBuilder {
+        self.opts.reconcile = reconcile;
+        self
+    }
+
+    pub fn default_size(mut self, default_size: Option<u32>) -> UnlitOptionsBuilder {
+        self.opts.default_size = default_size;
+        self
+    }
+
+    pub fn premultiply(mut self, premultiply: bool) -> UnlitOptionsBuilder {
+        self.opts.premultiply = premultiply;
+        self
+    }
+
+    pub fn scale(mut self, scale: f32) -> UnlitOptionsBuilder {
+        self.opts.scale = scale;
+        self
+    }
+
+    pub fn ao_floor(mut self, ao_floor: f32) -> UnlitOptionsBuilder {
+        self.opts.ao_floor = ao_floor;
+        self
+    }
+
+    pub fn ao_gamma(mut self, ao_gamma: f32) -> UnlitOptionsBuilder {
+        self.opts.ao_gamma = ao_gamma;
+        self
+    }
+
+    pub fn occlusion_channel(mut self, occlusion_channel: OcclusionChannel) -> UnlitOptionsBuilder {
+        self.opts.occlusion_channel = occlusion_channel;
+        self
+    }
+
+    pub fn occlusion_strength(mut self, occlusion_strength: Option<f32>) -> UnlitOptionsBuilder {
+        self.opts.occlusion_strength = occlusion_strength;
+        self
+    }
+
+    pub fn extra_occlusion(mut self, extra_occlusion: Option<Arc<RgbImage>>) -> UnlitOptionsBuilder {
+        self.opts.extra_occlusion = extra_occlusion;
+        self
+    }
+
+    pub fn dither(mut self, dither: bool) -> UnlitOptionsBuilder {
+        self.opts.dither = dither;
+        self
+    }
+
+    pub fn power_of_two(mut self, power_of_two: Option<PowerOfTwo>) -> UnlitOptionsBuilder {
+        self.opts.power_of_two = power_of_two;
+        self
+    }
+
+    pub fn max_dimension(mut self, max_dimension: Option<u32>) -> UnlitOptionsBuilder {
+        self.opts.max_dimension = max_dimension;
+        self
+    }
+
+    pub fn sanity_check(mut self, sanity_check: bool) -> UnlitOptionsBuilder {
+        self.opts.sanity_check = sanity_check;
+        self
+    }
+
+    pub fn solid(mut self, solid: bool) -> UnlitOptionsBuilder {
+        self.opts.solid = solid;
+        self
+    }
+
+    pub fn flip_v(mut self, flip_v: bool) -> UnlitOptionsBuilder {
+        self.opts.flip_v = flip_v;
+        self
+    }
+
+    pub fn tile_height(mut self, tile_height: Option<u32>) -> UnlitOptionsBuilder {
+        self.opts.tile_height = tile_height;
+        self
+    }
+
+    pub fn build(self) -> UnlitOptions {
+        self.opts
+    }
+}
+
+impl Default for UnlitOptionsBuilder {
+    fn default() -> UnlitOptionsBuilder {
+        UnlitOptionsBuilder::new()
+    }
+}
+
+/// Filter used to resample input maps to a common size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3
+}
+
+impl ResizeFilter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3
+        }
+    }
+}
+
+impl FromStr for ResizeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ResizeFilter, String> {
+        match s {
+            "nearest" => Ok(ResizeFilter::Nearest),
+            "triangle" => Ok(ResizeFilter::Triangle),
+            "catmullrom" => Ok(ResizeFilter::CatmullRom),
+            "gaussian" => Ok(ResizeFilter::Gaussian),
+            "lanczos3" => Ok(ResizeFilter::Lanczos3),
+            _ => Err(format!("Unknown resize filter: {}", s))
+        }
+    }
+}
+
+/// Statistics of each RGBA channel of a texture, for tuning the bake.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureStats {
+    pub min: [u8; 4],
+    pub max: [u8; 4],
+    pub mean: [f32; 4],
+    /// The fraction of texels at 255, which is where aggressive lighten, exposure,
+    /// or emissive clips.
+    pub saturated: [f32; 4]
+}
+
+/// Computes the [`TextureStats`] of every channel of `img`.
+pub fn texture_stats(img: &RgbaImage) -> TextureStats {
+    let mut min = [255u8; 4];
+    let mut max = [0u8; 4];
+    let mut sum = [0u64; 4];
+    let mut saturated = [0u64; 4];
+    for pixel in img.pixels() {
+        for (i, &value) in pixel.data.iter().enumerate() {
+            min[i] = min[i].min(value);
+            max[i] = max[i].max(value);
+            sum[i] += value as u64;
+            if value == 255 {
+                saturated[i] += 1;
+            }
+        }
+    }
+    let count = (img.width() as u64 * img.height() as u64).max(1) as f64;
+    let fraction = |values: [u64; 4]| [
+        (values[0] as f64 / count) as f32,
+        (values[1] as f64 / count) as f32,
+        (values[2] as f64 / count) as f32,
+        (values[3] as f64 / count) as f32
+    ];
+    TextureStats { min, max, mean: fraction(sum), saturated: fraction(saturated) }
+}
+
+/// Filter used to downsample each level of a mip chain from the one above it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipFilter {
+    /// Averages each 2x2 block of texels.
+    Box,
+    Resize(ResizeFilter)
+}
+
+impl FromStr for MipFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MipFilter, String> {
+        match s {
+            "box" => Ok(MipFilter::Box),
+            _ => s.parse::<ResizeFilter>().map(MipFilter::Resize).map_err(|_| format!("Unknown mip filter: {}", s))
+        }
+    }
+}
+
+/// Where `UnlitOptions::occlusion_channel` reads occlusion from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OcclusionChannel {
+    Red,
+    Green,
+    Blue,
+    /// The Rec. 709 luminance of the RGB channels.
+    Luminance
+}
+
+impl FromStr for OcclusionChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OcclusionChannel, String> {
+        match s {
+            "r" | "red" => Ok(OcclusionChannel::Red),
+            "g" | "green" => Ok(OcclusionChannel::Green),
+            "b" | "blue" => Ok(OcclusionChannel::Blue),
+            "luminance" => Ok(OcclusionChannel::Luminance),
+            _ => Err(format!("Unknown occlusion channel: {}", s))
+        }
+    }
+}
+
+/// How `UnlitOptions::power_of_two` rounds the dimensions of a texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerOfTwo {
+    Up,
+    Down
+}
+
+impl FromStr for PowerOfTwo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PowerOfTwo, String> {
+        match s {
+            "up" => Ok(PowerOfTwo::Up),
+            "down" => Ok(PowerOfTwo::Down),
+            _ => Err(format!("Unknown power of two mode: {}", s))
+        }
+    }
+}
+
+/// How `UnlitOptions::reconcile` fits maps of differing sizes to the output
+/// without resampling them. Each map is centered on the output, cropping the
+/// texels that overhang it and padding where it does not cover the output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reconcile {
+    /// The output is the size of the base color map, or failing that the
+    /// emissive or metallic roughness map.
+    Crop,
+    /// The output is as wide and as tall as the largest maps, so no texels are
+    /// cropped.
+    Pad
+}
+
+impl FromStr for Reconcile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Reconcile, String> {
+        match s {
+            "crop" => Ok(Reconcile::Crop),
+            "pad" => Ok(Reconcile::Pad),
+            _ => Err(format!("Unknown reconcile mode: {}", s))
+        }
+    }
+}
+
+/// One contribution to an unlit texture, which [`generate_component`] bakes on
+/// its own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Component {
+    /// The base color with its factor, toning, and metal darkening applied.
+    Base,
+    /// The occlusion factor as gray.
+    Occlusion,
+    /// The emissive map multiplied by its factor.
+    Emissive
+}
+
+impl Component {
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Base => "base",
+            Component::Occlusion => "occlusion",
+            Component::Emissive => "emissive"
+        }
+    }
+}
+
+impl FromStr for Component {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Component, String> {
+        match s {
+            "base" => Ok(Component::Base),
+            "occlusion" => Ok(Component::Occlusion),
+            "emissive" => Ok(Component::Emissive),
+            _ => Err(format!("Unknown component: {}", s))
+        }
+    }
+}
+
+/// An RGBA image with linear floating point channels in the range 0.0 - 1.0.
+pub type LinearImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// An RGBA image with 16-bit channels, as written by [`png16::encode_png16`].
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Decodes an 8-bit sRGB channel value to linear light.
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear light value to an 8-bit sRGB channel value, clamping to 0.0 - 1.0.
+pub fn linear_to_srgb(value: f32) -> u8 {
+    (srgb_encode(value) * 255.0).round() as u8
+}
+
+/// Encodes a linear light value to sRGB, clamping to 0.0 - 1.0.
+fn srgb_encode(value: f32) -> f32 {
+    let c = value.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Thresholds of a 4x4 Bayer matrix, which orders the dither of neighbouring texels.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The ordered dither offset of texel (x, y), a fraction of an 8-bit step
+/// between -0.5 and 0.5.
+fn dither_offset(x: u32, y: u32) -> f32 {
+    (BAYER_4X4[(y % 4) as usize][(x % 4) as usize] as f32 + 0.5) / 16.0 - 0.5
+}
+
+/// Quantizes a 0.0 - 1.0 value to 8 bits, offset by `dither` of a step before
+/// rounding.
+fn quantize_dithered(value: f32, dither: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0 + dither).round().clamp(0.0, 255.0) as u8
+}
+
+/// Encodes a linear light value to a 16-bit sRGB channel value, clamping to 0.0 - 1.0.
+pub fn linear_to_srgb16(value: f32) -> u16 {
+    let c = value.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 65535.0).round() as u16
+}
+
+/// Returns the shared dimensions of the input maps, or an error if they differ
+/// or no maps were provided.
+pub fn validate_dimensions<I: Iterator<Item=(u32, u32)>>(dimensions: I) -> Result<(u32, u32), UnlitError> {
+    let mut candidate = None;
+    for d in dimensions {
+        match candidate {
+            Some(expected) if expected != d => return Err(UnlitError::DimensionMismatch { expected, found: d }),
+            _ => candidate = Some(d)
+        }
+    }
+    candidate.ok_or(UnlitError::MissingTexture)
+}
+
+/// A KHR_texture_transform applied to the UVs of a texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureTransform {
+    pub offset: [f32; 2],
+    /// Counterclockwise rotation in radians.
+    pub rotation: f32,
+    pub scale: [f32; 2]
+}
+
+impl Default for TextureTransform {
+    fn default() -> TextureTransform {
+        TextureTransform { offset: [0.0, 0.0], rotation: 0.0, scale: [1.0, 1.0] }
+    }
+}
+
+impl TextureTransform {
+    /// Reads the KHR_texture_transform of a raw texture info JSON object, or the
+    /// identity transform when the extension is absent.
+    pub fn from_info_json(info_json: &JsonValue) -> TextureTransform {
+        let ext = &info_json["extensions"]["KHR_texture_transform"];
+        let pair = |value: &JsonValue, default: [f32; 2]| [
+            value[0].as_f64().map_or(default[0], |v| v as f32),
+            value[1].as_f64().map_or(default[1], |v| v as f32)
+        ];
+        let identity = TextureTransform::default();
+        TextureTransform {
+            offset: pair(&ext["offset"], identity.offset),
+            rotation: ext["rotation"].as_f64().map_or(identity.rotation, |r| r as f32),
+            scale: pair(&ext["scale"], identity.scale)
+        }
+    }
+
+    /// The transform as the rows of a 2x3 affine matrix, which per the extension
+    /// scales, then rotates, then offsets the UVs.
+    fn matrix(&self) -> [[f32; 3]; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            [cos * self.scale[0], sin * self.scale[1], self.offset[0]],
+            [-sin * self.scale[0], cos * self.scale[1], self.offset[1]]
+        ]
+    }
+}
+
+/// Maps the UVs of one texture to the UVs of another texture with a different
+/// KHR_texture_transform over the same texCoord set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvTransform {
+    matrix: [[f32; 3]; 2]
+}
+
+impl UvTransform {
+    /// The transform taking the UVs of a texture transformed by `from` to the UVs
+    /// of a texture transformed by `to`, undoing `from` and then applying `to`.
+    pub fn between(from: &TextureTransform, to: &TextureTransform) -> UvTransform {
+        let [[a, b, c], [d, e, f]] = from.matrix();
+        // Invert the affine `from`, whose scale is never zero in a valid glTF
+        let det = a * e - b * d;
+        let inverse = [
+            [e / det, -b / det, (b * f - c * e) / det],
+            [-d / det, a / det, (c * d - a * f) / det]
+        ];
+        let to = to.matrix();
+        let mut matrix = [[0.0; 3]; 2];
+        for row in 0..2 {
+            for col in 0..3 {
+                matrix[row][col] = to[row][0] * inverse[0][col] + to[row][1] * inverse[1][col];
+            }
+            matrix[row][2] += to[row][2];
+        }
+        UvTransform { matrix }
+    }
+
+    pub fn apply(&self, uv: [f32; 2]) -> [f32; 2] {
+        let [[a, b, c], [d, e, f]] = self.matrix;
+        [a * uv[0] + b * uv[1] + c, d * uv[0] + e * uv[1] + f]
+    }
+}
+
+/// Returns the TEXCOORD_n set a raw texture info JSON object samples, including
+/// the override allowed by KHR_texture_transform.
+pub fn tex_coord(info_json: &JsonValue) -> u32 {
+    let tex_coord = info_json["extensions"]["KHR_texture_transform"]["texCoord"].as_u64()
+        .or_else(|| info_json["texCoord"].as_u64());
+    tex_coord.unwrap_or(0) as u32
+}
+
+/// Checks that every named map samples the same texCoord set with the same
+/// texture transform. Maps are combined texel by texel, which is only valid when
+/// their UV layouts line up.
+pub fn validate_uv_layouts<I: Iterator<Item=(&'static str, u32, TextureTransform)>>(layouts: I) -> Result<(), UnlitError> {
+    let mut reference = None;
+    for (map, tex_coord, transform) in layouts {
+        match reference {
+            Some((reference, reference_tex_coord, _)) if reference_tex_coord != tex_coord => {
+                return Err(UnlitError::TexCoordMismatch { map, tex_coord, reference, reference_tex_coord });
+            },
+            Some((reference, _, reference_transform)) if reference_transform != transform => {
+                return Err(UnlitError::TransformMismatch { map, reference });
+            },
+            Some(_) => {},
+            None => reference = Some((map, tex_coord, transform))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the dimensions of the largest input map by area, or an error if no
+/// maps were provided.
+pub fn largest_dimensions<I: Iterator<Item=(u32, u32)>>(dimensions: I) -> Result<(u32, u32), UnlitError> {
+    dimensions.max_by_key(|&(w, h)| w as u64 * h as u64).ok_or(UnlitError::MissingTexture)
+}
+
+/// The sampler wrap modes of a texture along its s and t axes.
+pub type WrapModes = (WrappingMode, WrappingMode);
+
+/// Resamples `img` to `w` by `h`. Axes whose wrap mode repeats are padded with
+/// wrapped or mirrored texels first, so the filter blends edges with the opposite
+/// side of the texture instead of clamping them.
+fn resize_to<P>(img: ImageBuffer<P, Vec<u8>>, w: u32, h: u32, filter: ResizeFilter, wrap: WrapModes) -> ImageBuffer<P, Vec<u8>>
+    where P: Pixel<Subpixel=u8> + 'static
+{
+    let (old_w, old_h) = img.dimensions();
+    if (old_w, old_h) == (w, h) {
+        return img;
+    }
+
+    let (pad_x, target_pad_x) = wrap_padding(old_w, w, wrap.0);
+    let (pad_y, target_pad_y) = wrap_padding(old_h, h, wrap.1);
+    if pad_x == 0 && pad_y == 0 {
+        return imageops::resize(&img, w, h, filter.filter_type());
+    }
+
+    let padded = ImageBuffer::from_fn(old_w + 2 * pad_x, old_h + 2 * pad_y, |x, y| {
+        let src_x = wrap_coordinate(x as i64 - pad_x as i64, old_w, wrap.0);
+        let src_y = wrap_coordinate(y as i64 - pad_y as i64, old_h, wrap.1);
+        *img.get_pixel(src_x, src_y)
+    });
+    let mut resized = imageops::resize(&padded, w + 2 * target_pad_x, h + 2 * target_pad_y, filter.filter_type());
+    imageops::crop(&mut resized, target_pad_x, target_pad_y, w, h).to_image()
+}
+
+/// Returns how many source and target texels to pad an axis with. The two are
+/// kept in exactly the same ratio as the resize so the padded result lines up
+/// with the unpadded texels. Clamped axes need no padding.
+fn wrap_padding(old: u32, new: u32, mode: WrappingMode) -> (u32, u32) {
+    // The widest filters reach 3 texels to either side
+    const FILTER_SUPPORT: u32 = 4;
+
+    if let WrappingMode::ClampToEdge = mode {
+        return (0, 0);
+    }
+    let divisor = gcd(old, new);
+    let (source_step, target_step) = (old / divisor, new / divisor);
+    let steps = FILTER_SUPPORT.div_ceil(source_step);
+    (steps * source_step, steps * target_step)
+}
+
+fn wrap_coordinate(coordinate: i64, size: u32, mode: WrappingMode) -> u32 {
+    let size = size as i64;
+    let wrapped = match mode {
+        WrappingMode::ClampToEdge => coordinate.max(0).min(size - 1),
+        WrappingMode::Repeat => ((coordinate % size) + size) % size,
+        WrappingMode::MirroredRepeat => {
+            let period = 2 * size;
+            let c = ((coordinate % period) + period) % period;
+            if c < size { c } else { period - 1 - c }
+        }
+    };
+    wrapped as u32
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Calls `f` with every band of at most `tile_height` rows of `img` and the row
+/// the band starts at, processing the bands in parallel. Without a tile height
+/// the whole image is a single band.
+fn for_each_tile<P, F>(img: &mut ImageBuffer<P, Vec<P::Subpixel>>, tile_height: Option<u32>, f: F)
+    where P: Pixel + 'static, P::Subpixel: Send + 'static, F: Fn(&mut ImageBuffer<P, &mut [P::Subpixel]>, u32) + Sync
+{
+    let (w, h) = img.dimensions();
+    let row_len = w as usize * P::channel_count() as usize;
+    match tile_height {
+        Some(tile_height) if row_len > 0 => {
+            let tile_height = tile_height.max(1);
+            img.par_chunks_mut(row_len * tile_height as usize).enumerate().for_each(|(index, rows)| {
+                let rows_in_tile = (rows.len() / row_len) as u32;
+                let mut tile = ImageBuffer::from_raw(w, rows_in_tile, rows).unwrap();
+                f(&mut tile, index as u32 * tile_height);
+            });
+        },
+        _ => {
+            let mut tile = ImageBuffer::from_raw(w, h, &mut **img).unwrap();
+            f(&mut tile, 0);
+        }
+    }
+}
+
+/// Bilinearly samples the red channel of `map` at the center of texel `x`, `y` of
+/// a `w` by `h` image covering the same UV space, clamping at the edges.
+fn sample_red_bilinear(map: &RgbImage, x: u32, y: u32, w: u32, h: u32) -> f32 {
+    let (map_w, map_h) = map.dimensions();
+    // The texel centers of both images are aligned. Maps of the same size sample
+    // exactly one texel since every term of this is exact in floating point.
+    let source = |t: u32, size: u32, map_size: u32| {
+        let s = ((2 * t + 1) * map_size) as f32 / (2 * size) as f32 - 0.5;
+        let s = s.max(0.0).min((map_size - 1) as f32);
+        let s0 = s.floor() as u32;
+        (s0, (s0 + 1).min(map_size - 1), s - s0 as f32)
+    };
+    let (x0, x1, fx) = source(x, w, map_w);
+    let (y0, y1, fy) = source(y, h, map_h);
+    let top = map.get_pixel(x0, y0)[0] as f32 * (1.0 - fx) + map.get_pixel(x1, y0)[0] as f32 * fx;
+    let bottom = map.get_pixel(x0, y1)[0] as f32 * (1.0 - fx) + map.get_pixel(x1, y1)[0] as f32 * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Bilinearly samples the red channel of `map` at the center of texel `x`, `y` of
+/// a `w` by `h` image whose UVs `transform` maps to the UVs of `map`, wrapping
+/// by the map's sampler.
+fn sample_red_transformed(map: &RgbImage, x: u32, y: u32, w: u32, h: u32, transform: &UvTransform, wrap: WrapModes) -> f32 {
+    let (map_w, map_h) = map.dimensions();
+    let uv = transform.apply([(x as f32 + 0.5) / w as f32, (y as f32 + 0.5) / h as f32]);
+    let s = uv[0] * map_w as f32 - 0.5;
+    let t = uv[1] * map_h as f32 - 0.5;
+    let (s0, t0) = (s.floor(), t.floor());
+    let (fx, fy) = (s - s0, t - t0);
+    let (s0, t0) = (s0 as i64, t0 as i64);
+    let (x0, x1) = (wrap_coordinate(s0, map_w, wrap.0), wrap_coordinate(s0 + 1, map_w, wrap.0));
+    let (y0, y1) = (wrap_coordinate(t0, map_h, wrap.1), wrap_coordinate(t0 + 1, map_h, wrap.1));
+    let top = map.get_pixel(x0, y0)[0] as f32 * (1.0 - fx) + map.get_pixel(x1, y0)[0] as f32 * fx;
+    let bottom = map.get_pixel(x0, y1)[0] as f32 * (1.0 - fx) + map.get_pixel(x1, y1)[0] as f32 * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Moves the `channel` occlusion is stored in to the red channel of
+/// `occlusion_map`, which is where the occlusion kernels read it. The green and
+/// blue channels are left as they are for a packed metallic roughness map.
+pub fn select_occlusion_channel(occlusion_map: &mut RgbImage, channel: OcclusionChannel) {
+    if channel == OcclusionChannel::Red {
+        return;
+    }
+    for pixel in occlusion_map.pixels_mut() {
+        let [r, g, b] = pixel.data;
+        pixel.data[0] = match channel {
+            OcclusionChannel::Red => r,
+            OcclusionChannel::Green => g,
+            OcclusionChannel::Blue => b,
+            OcclusionChannel::Luminance => (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8
+        };
+    }
+}
+
+/// Multiplies the RGB channels of `img` by the red channel of the occlusion map,
+/// scaled by the occlusion strength. The factor is clamped to 1.0 so occlusion
+/// never brightens the base color, raised to `1 / gamma`, and kept to at least
+/// `floor`.
+///
+/// An occlusion map of a different size than `img` is sampled bilinearly, so
+/// maps authored at a lower resolution stay smooth.
+pub fn apply_occlusion(img: &mut RgbaImage, occlusion_map: &RgbImage, strength: f32, gamma: f32, floor: f32) {
+    let (_, h) = img.dimensions();
+    occlude_rows(img, 0, h, occlusion_map, None, OcclusionParams { strength, softened: false, gamma, floor });
+}
+
+/// The strength, gamma, and floor occlusion is applied with.
+#[derive(Clone, Copy, Debug)]
+struct OcclusionParams {
+    strength: f32,
+    /// Whether `strength` is `UnlitOptions::occlusion_strength`, which softens
+    /// occlusion towards none instead of scaling it.
+    softened: bool,
+    gamma: f32,
+    floor: f32
+}
+
+impl OcclusionParams {
+    /// Occlusion at `strength` with the gamma and floor of `opts`.
+    fn new(strength: f32, opts: &UnlitOptions) -> OcclusionParams {
+        OcclusionParams { strength, softened: opts.occlusion_strength.is_some(), gamma: opts.ao_gamma, floor: opts.ao_floor }
+    }
+
+    /// The factor a texel with `occlusion` 0 - 255 is occluded by.
+    fn factor(&self, occlusion: f32) -> f32 {
+        let factor = if self.softened {
+            // Like glTF's occlusion strength, 0.0 is no occlusion and 1.0 is full
+            1.0 + self.strength * (occlusion / 255.0 - 1.0)
+        } else {
+            occlusion * (self.strength / 255.0)
+        };
+        let factor = factor.clamp(0.0, 1.0);
+        let factor = if self.gamma == 1.0 { factor } else { factor.powf(1.0 / self.gamma) };
+        factor.max(self.floor)
+    }
+}
+
+/// Samples the red channel of the occlusion map for texel `x`, `y` of a `w` by
+/// `h` image, through `uv` if the map has its own texture transform.
+fn sample_occlusion(occlusion_map: &RgbImage, uv: Option<&(UvTransform, WrapModes)>, x: u32, y: u32, w: u32, h: u32) -> f32 {
+    match uv {
+        Some(&(ref transform, wrap)) => sample_red_transformed(occlusion_map, x, y, w, h, transform, wrap),
+        None => sample_red_bilinear(occlusion_map, x, y, w, h)
+    }
+}
+
+/// [`apply_occlusion`] on the rows of an image `h` rows tall starting at row `y0`.
+fn occlude_rows<C>(img: &mut ImageBuffer<Rgba<u8>, C>, y0: u32, h: u32, occlusion_map: &RgbImage, uv: Option<&(UvTransform, WrapModes)>, params: OcclusionParams)
+    where C: Deref<Target=[u8]> + DerefMut
+{
+    let (w, _) = img.dimensions();
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        // Occlusion is on the red channel of the occlusion texture
+        let occlusion_factor = params.factor(sample_occlusion(occlusion_map, uv, x, y0 + y, w, h));
+        pixel.data[0] = (pixel.data[0] as f32 * occlusion_factor) as u8;
+        pixel.data[1] = (pixel.data[1] as f32 * occlusion_factor) as u8;
+        pixel.data[2] = (pixel.data[2] as f32 * occlusion_factor) as u8;
+    }
+}
+
+/// The multiplier applied to the base color of a texel to approximate the specular
+/// reflection an unlit material loses. Smooth, metallic texels darken the most.
+pub fn metal_darkening(metallic: f32, roughness: f32, strength: f32) -> f32 {
+    (1.0 - strength * metallic * (1.0 - 0.5 * roughness)).clamp(0.0, 1.0)
+}
+
+/// Darkens the RGB channels of `img` by [`metal_darkening`]. Metalness is read from
+/// the blue and roughness from the green channel of the metallic roughness map,
+/// scaled by their factors. Without a map the factors apply to every texel.
+pub fn apply_metal_darken(img: &mut RgbaImage, metallic_roughness_map: Option<&RgbImage>, metallic_factor: f32, roughness_factor: f32, strength: f32) {
+    darken_metal_rows(img, 0, metallic_roughness_map, metallic_factor, roughness_factor, strength);
+}
+
+/// [`apply_metal_darken`] on the rows of an image starting at row `y0`.
+fn darken_metal_rows<C>(img: &mut ImageBuffer<Rgba<u8>, C>, y0: u32, metallic_roughness_map: Option<&RgbImage>, metallic_factor: f32, roughness_factor: f32, strength: f32)
+    where C: Deref<Target=[u8]> + DerefMut
+{
+    let (w, h) = img.dimensions();
+    for y in 0..h {
+        for x in 0..w {
+            let (metallic, roughness) = match metallic_roughness_map {
+                Some(map) => {
+                    let mr = map.get_pixel(x, y0 + y);
+                    (mr[2] as f32 / 255.0 * metallic_factor, mr[1] as f32 / 255.0 * roughness_factor)
+                },
+                None => (metallic_factor, roughness_factor)
+            };
+            let darkening = metal_darkening(metallic, roughness, strength);
+            let pixel = img.get_pixel_mut(x, y);
+            pixel.data[0] = (pixel.data[0] as f32 * darkening) as u8;
+            pixel.data[1] = (pixel.data[1] as f32 * darkening) as u8;
+            pixel.data[2] = (pixel.data[2] as f32 * darkening) as u8;
+        }
+    }
+}
+
+/// Adds the emissive map, tinted by the emissive color, to the RGB channels of `img`.
+/// Color components above 1.0 are allowed and clip at full intensity.
+pub fn apply_emissive(img: &mut RgbaImage, emissive_map: &RgbImage, color: [f32; 3]) {
+    add_emissive_rows(img, 0, emissive_map, color);
+}
+
+/// [`apply_emissive`] on the rows of an image starting at row `y0`.
+fn add_emissive_rows<C>(img: &mut ImageBuffer<Rgba<u8>, C>, y0: u32, emissive_map: &RgbImage, color: [f32; 3])
+    where C: Deref<Target=[u8]> + DerefMut
+{
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let em = emissive_map.get_pixel(x, y0 + y);
+        // Summed unclamped and clamped once, instead of clamping the emissive too
+        pixel.data[0] = (pixel.data[0] as f32 + em.data[0] as f32 * color[0]).min(255.0) as u8;
+        pixel.data[1] = (pixel.data[1] as f32 + em.data[1] as f32 * color[1]).min(255.0) as u8;
+        pixel.data[2] = (pixel.data[2] as f32 + em.data[2] as f32 * color[2]).min(255.0) as u8;
+    }
+}
+
+/// Adds a constant emissive `color`, scaled to 0 - 255, to every texel, as
+/// emitted by a material without an emissive map.
+fn add_flat_emissive<C>(img: &mut ImageBuffer<Rgba<u8>, C>, color: [f32; 3])
+    where C: Deref<Target=[u8]> + DerefMut
+{
+    for pixel in img.pixels_mut() {
+        pixel.data[0] = (pixel.data[0] as f32 + 255.0 * color[0]).min(255.0) as u8;
+        pixel.data[1] = (pixel.data[1] as f32 + 255.0 * color[1]).min(255.0) as u8;
+        pixel.data[2] = (pixel.data[2] as f32 + 255.0 * color[2]).min(255.0) as u8;
+    }
+}
+
+/// Multiplies the RGB channels of `img` by its alpha channel.
+pub fn premultiply_alpha(img: &mut RgbaImage) {
+    for pixel in img.pixels_mut() {
+        let alpha = pixel.data[3] as u32;
+        pixel.data[0] = ((pixel.data[0] as u32 * alpha + 127) / 255) as u8;
+        pixel.data[1] = ((pixel.data[1] as u32 * alpha + 127) / 255) as u8;
+        pixel.data[2] = ((pixel.data[2] as u32 * alpha + 127) / 255) as u8;
+    }
+}
+
+/// Composites `img` over a solid `background` color, leaving every texel opaque.
+/// With `premultiplied` the RGB channels of `img` are already multiplied by alpha.
+pub fn flatten_onto(img: &mut RgbaImage, background: [u8; 3], premultiplied: bool) {
+    for pixel in img.pixels_mut() {
+        let alpha = pixel.data[3] as u32;
+        for (value, &background) in pixel.data.iter_mut().zip(&background) {
+            let foreground = *value as u32 * if premultiplied { 255 } else { alpha };
+            let composited = (foreground + background as u32 * (255 - alpha) + 127) / 255;
+            *value = composited.min(255) as u8;
+        }
+        pixel.data[3] = 255;
+    }
+}
+
+/// Linear light variant of [`apply_occlusion`]. Occlusion maps are already linear.
+pub fn apply_occlusion_linear(img: &mut LinearImage, occlusion_map: &RgbImage, strength: f32, gamma: f32, floor: f32) {
+    let (_, h) = img.dimensions();
+    occlude_rows_linear(img, 0, h, occlusion_map, None, OcclusionParams { strength, softened: false, gamma, floor });
+}
+
+fn occlude_rows_linear<C>(img: &mut ImageBuffer<Rgba<f32>, C>, y0: u32, h: u32, occlusion_map: &RgbImage, uv: Option<&(UvTransform, WrapModes)>, params: OcclusionParams)
+    where C: Deref<Target=[f32]> + DerefMut
+{
+    let (w, _) = img.dimensions();
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let occlusion_factor = params.factor(sample_occlusion(occlusion_map, uv, x, y0 + y, w, h));
+        pixel.data[0] *= occlusion_factor;
+        pixel.data[1] *= occlusion_factor;
+        pixel.data[2] *= occlusion_factor;
+    }
+}
+
+/// Linear light variant of [`apply_metal_darken`].
+pub fn apply_metal_darken_linear(img: &mut LinearImage, metallic_roughness_map: Option<&RgbImage>, metallic_factor: f32, roughness_factor: f32, strength: f32) {
+    darken_metal_rows_linear(img, 0, metallic_roughness_map, metallic_factor, roughness_factor, strength);
+}
+
+fn darken_metal_rows_linear<C>(img: &mut ImageBuffer<Rgba<f32>, C>, y0: u32, metallic_roughness_map: Option<&RgbImage>, metallic_factor: f32, roughness_factor: f32, strength: f32)
+    where C: Deref<Target=[f32]> + DerefMut
+{
+    let (w, h) = img.dimensions();
+    for y in 0..h {
+        for x in 0..w {
+            let (metallic, roughness) = match metallic_roughness_map {
+                Some(map) => {
+                    let mr = map.get_pixel(x, y0 + y);
+                    (mr[2] as f32 / 255.0 * metallic_factor, mr[1] as f32 / 255.0 * roughness_factor)
+                },
+                None => (metallic_factor, roughness_factor)
+            };
+            let darkening = metal_darkening(metallic, roughness, strength);
+            let pixel = img.get_pixel_mut(x, y);
+            pixel.data[0] *= darkening;
+            pixel.data[1] *= darkening;
+            pixel.data[2] *= darkening;
+        }
+    }
+}
+
+/// Linear light variant of [`apply_emissive`]. The emissive map is decoded from sRGB.
+pub fn apply_emissive_linear(img: &mut LinearImage, emissive_map: &RgbImage, color: [f32; 3]) {
+    add_emissive_rows_linear(img, 0, emissive_map, color);
+}
+
+fn add_emissive_rows_linear<C>(img: &mut ImageBuffer<Rgba<f32>, C>, y0: u32, emissive_map: &RgbImage, color: [f32; 3])
+    where C: Deref<Target=[f32]> + DerefMut
+{
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let em = emissive_map.get_pixel(x, y0 + y);
+        pixel.data[0] += srgb_to_linear(em.data[0]) * color[0];
+        pixel.data[1] += srgb_to_linear(em.data[1]) * color[1];
+        pixel.data[2] += srgb_to_linear(em.data[2]) * color[2];
+    }
+}
+
+/// [`add_flat_emissive`] in linear light.
+fn add_flat_emissive_linear<C>(img: &mut ImageBuffer<Rgba<f32>, C>, color: [f32; 3])
+    where C: Deref<Target=[f32]> + DerefMut
+{
+    for pixel in img.pixels_mut() {
+        pixel.data[0] += color[0];
+        pixel.data[1] += color[1];
+        pixel.data[2] += color[2];
+    }
+}
+
+/// Applies exposure and then gamma to a linear color channel.
+pub fn tone(value: f32, exposure: f32, gamma: f32) -> f32 {
+    (value * exposure).powf(1.0 / gamma)
+}
+
+/// Decodes an sRGB base color map to linear light, multiplying in the base color
+/// factor, applying `exposure` and `gamma`, and adding `lighten` to each RGB channel.
+pub fn linear_base_color(base_map: &RgbaImage, color_factor: [f32; 4], exposure: f32, gamma: f32, lighten: [f32; 3]) -> LinearImage {
+    let (w, h) = base_map.dimensions();
+    let mut img = LinearImage::new(w, h);
+    for (pixel, base) in img.pixels_mut().zip(base_map.pixels()) {
+        pixel.data[0] = tone(srgb_to_linear(base.data[0]) * color_factor[0], exposure, gamma) + lighten[0];
+        pixel.data[1] = tone(srgb_to_linear(base.data[1]) * color_factor[1], exposure, gamma) + lighten[1];
+        pixel.data[2] = tone(srgb_to_linear(base.data[2]) * color_factor[2], exposure, gamma) + lighten[2];
+        pixel.data[3] = base.data[3] as f32 / 255.0 * color_factor[3];
+    }
+    img
+}
+
+/// Encodes a linear image back to 8-bit sRGB. Alpha is stored linearly.
+pub fn encode_srgb(img: &LinearImage) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let mut encoded = RgbaImage::new(w, h);
+    for (pixel, linear) in encoded.pixels_mut().zip(img.pixels()) {
+        pixel.data[0] = linear_to_srgb(linear.data[0]);
+        pixel.data[1] = linear_to_srgb(linear.data[1]);
+        pixel.data[2] = linear_to_srgb(linear.data[2]);
+        pixel.data[3] = (linear.data[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    encoded
+}
+
+/// [`encode_srgb`] with an ordered dither, which breaks up the banding of smooth
+/// gradients. Alpha is not dithered.
+pub fn encode_srgb_dithered(img: &LinearImage) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let mut encoded = RgbaImage::new(w, h);
+    for (x, y, pixel) in encoded.enumerate_pixels_mut() {
+        let linear = img.get_pixel(x, y);
+        let dither = dither_offset(x, y);
+        for channel in 0..3 {
+            pixel.data[channel] = quantize_dithered(srgb_encode(linear.data[channel]), dither);
+        }
+        pixel.data[3] = (linear.data[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    encoded
+}
+
+/// Encodes a linear image to 16-bit sRGB, keeping more of the precision of the
+/// linear bake than [`encode_srgb`]. Alpha is stored linearly.
+pub fn encode_srgb16(img: &LinearImage) -> Rgba16Image {
+    let (w, h) = img.dimensions();
+    let mut encoded = Rgba16Image::new(w, h);
+    for (pixel, linear) in encoded.pixels_mut().zip(img.pixels()) {
+        pixel.data[0] = linear_to_srgb16(linear.data[0]);
+        pixel.data[1] = linear_to_srgb16(linear.data[1]);
+        pixel.data[2] = linear_to_srgb16(linear.data[2]);
+        pixel.data[3] = (linear.data[3].clamp(0.0, 1.0) * 65535.0).round() as u16;
+    }
+    encoded
+}
+
+/// Quantizes a linear image to 8 bits without encoding it to sRGB. Alpha is
+/// stored linearly either way.
+pub fn encode_linear(img: &LinearImage) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let mut encoded = RgbaImage::new(w, h);
+    for (pixel, linear) in encoded.pixels_mut().zip(img.pixels()) {
+        for channel in 0..4 {
+            pixel.data[channel] = (linear.data[channel].clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+    encoded
+}
+
+/// [`encode_linear`] with an ordered dither. Alpha is not dithered.
+pub fn encode_linear_dithered(img: &LinearImage) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let mut encoded = RgbaImage::new(w, h);
+    for (x, y, pixel) in encoded.enumerate_pixels_mut() {
+        let linear = img.get_pixel(x, y);
+        let dither = dither_offset(x, y);
+        for channel in 0..3 {
+            pixel.data[channel] = quantize_dithered(linear.data[channel], dither);
+        }
+        pixel.data[3] = (linear.data[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    encoded
+}
+
+/// Converts the 8-bit sRGB RGB channels of `img` to 8-bit linear values.
+pub fn decode_srgb(img: &mut RgbaImage) {
+    for pixel in img.pixels_mut() {
+        pixel.data[0] = (srgb_to_linear(pixel.data[0]) * 255.0).round() as u8;
+        pixel.data[1] = (srgb_to_linear(pixel.data[1]) * 255.0).round() as u8;
+        pixel.data[2] = (srgb_to_linear(pixel.data[2]) * 255.0).round() as u8;
+    }
+}
+
+/// Creates a `w` by `h` image filled with the given RGBA color factor.
+pub fn generate_monocolor(w: u32, h: u32, color_factor: [f32; 4]) -> RgbaImage {
+    RgbaImage::from_pixel(w, h, Rgba::<u8>::from_channels(
+        (255.0 * color_factor[0]) as u8,
+        (255.0 * color_factor[1]) as u8,
+        (255.0 * color_factor[2]) as u8,
+        (255.0 * color_factor[3]) as u8
+    ))
+}
+
+/// Returns true if the raw material JSON declares the KHR_materials_unlit extension.
+pub fn is_unlit(mat_json: &JsonValue) -> bool {
+    !mat_json["extensions"]["KHR_materials_unlit"].is_null()
+}
+
+/// Returns the KHR_materials_pbrSpecularGlossiness extension of the raw material
+/// JSON, if it declares one.
+pub fn specular_glossiness(mat_json: &JsonValue) -> Option<&JsonValue> {
+    let extension = &mat_json["extensions"]["KHR_materials_pbrSpecularGlossiness"];
+    if extension.is_object() { Some(extension) } else { None }
+}
+
+/// Returns the raw texture info of the base color texture, which is the diffuse
+/// texture of specular glossiness materials.
+pub fn base_color_texture_json(mat_json: &JsonValue) -> &JsonValue {
+    match specular_glossiness(mat_json) {
+        Some(extension) => &extension["diffuseTexture"],
+        None => &mat_json["pbrMetallicRoughness"]["baseColorTexture"]
+    }
+}
+
+/// The base color factor of a material, which is the diffuse factor of specular
+/// glossiness materials.
+pub fn base_color_factor(mat: &Material, mat_json: &JsonValue) -> [f32; 4] {
+    match specular_glossiness(mat_json) {
+        Some(extension) => {
+            let mut factor = [1.0; 4];
+            if let Some(values) = extension["diffuseFactor"].as_array() {
+                for (factor, value) in factor.iter_mut().zip(values) {
+                    *factor = value.as_f64().unwrap_or(1.0) as f32;
+                }
+            }
+            factor
+        },
+        None => mat.pbr_metallic_roughness().base_color_factor()
+    }
+}
+
+/// Returns the KHR_materials_emissive_strength multiplier of the raw material JSON,
+/// or 1.0 when the extension is absent.
+///
+/// Strengths above 1.0 describe HDR emission. Since the unlit texture is 8-bit
+/// LDR, the emissive contribution clips at full intensity.
+pub fn emissive_strength(mat_json: &JsonValue) -> f32 {
+    mat_json["extensions"]["KHR_materials_emissive_strength"]["emissiveStrength"]
+        .as_f64()
+        .map_or(1.0, |strength| strength as f32)
+}
+
+/// The decoded input maps and factors of a material, resolved to a common size.
+///
+/// Maps are 8 bits per channel. The image crate decodes every format to 8 bits,
+/// and 16-bit PNGs are rounded to the nearest 8-bit value when they are loaded,
+/// so precision beyond that is lost even in the linear bake.
+#[derive(Clone, Debug)]
+pub struct MaterialMaps {
+    pub width: u32,
+    pub height: u32,
+    pub base_color_factor: [f32; 4],
+    pub base_map: Option<RgbaImage>,
+    pub occlusion_strength: f32,
+    /// Occlusion is read from the red channel, as the glTF spec requires, so the
+    /// map may be a packed occlusion, roughness, metallic (ORM) texture. Unlike the
+    /// other maps it keeps its own size and is sampled bilinearly.
+    pub occlusion_map: Option<RgbImage>,
+    /// Maps the UVs of the output to the occlusion map's, and how it wraps, when
+    /// its KHR_texture_transform differs from the other maps'.
+    pub occlusion_uv: Option<(UvTransform, WrapModes)>,
+    /// `UnlitOptions::extra_occlusion`, which is applied after the occlusion map.
+    pub extra_occlusion_map: Option<Arc<RgbImage>>,
+    /// The emissive factor with any emissive strength already multiplied in.
+    /// Without an emissive map it is added to every texel. It is zero for unlit
+    /// and `UnlitOptions::solid` materials, and when the emissive texture did
+    /// not load.
+    pub emissive_factor: [f32; 3],
+    pub emissive_map: Option<RgbImage>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Only loaded when `UnlitOptions::metal_darken` is enabled.
+    pub metallic_roughness_map: Option<RgbImage>,
+    /// The alpha cutoff of a MASK material. Output alpha is thresholded against it
+    /// to fully opaque or fully transparent.
+    pub alpha_cutoff: Option<f32>
+}
+
+/// The source images of a material's maps and their wrap modes, before they are
+/// converted and resolved to a common size.
+struct SourceMaps<M = Arc<DynamicImage>> {
+    base: Option<(M, WrapModes)>,
+    occlusion: Option<(M, WrapModes)>,
+    emissive: Option<(M, WrapModes)>,
+    metallic_roughness: Option<(M, WrapModes)>,
+    occlusion_uv: Option<(UvTransform, WrapModes)>
+}
+
+/// What [`load_source_maps`] loads each map as: its decoded image, or when only
+/// planning, its dimensions.
+trait SourceMap: Sized {
+    fn load(textures: &TextureCache, texture: &Texture) -> Option<Self>;
+    fn load_index(textures: &TextureCache, index: usize) -> Option<(Self, WrapModes)>;
+    fn dimensions(&self) -> (u32, u32);
+}
+
+impl SourceMap for Arc<DynamicImage> {
+    fn load(textures: &TextureCache, texture: &Texture) -> Option<Self> {
+        textures.load(texture)
+    }
+
+    fn load_index(textures: &TextureCache, index: usize) -> Option<(Self, WrapModes)> {
+        textures.load_index(index)
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (**self).dimensions()
+    }
+}
+
+impl SourceMap for (u32, u32) {
+    fn load(textures: &TextureCache, texture: &Texture) -> Option<Self> {
+        textures.load_dimensions(texture)
+    }
+
+    fn load_index(textures: &TextureCache, index: usize) -> Option<(Self, WrapModes)> {
+        textures.load_index_dimensions(index)
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        *self
+    }
+}
+
+impl<M: SourceMap> SourceMaps<M> {
+    fn dimensions(&self) -> [Option<(u32, u32)>; 4] {
+        let dimensions = |map: &Option<(M, WrapModes)>| map.as_ref().map(|(map, _)| map.dimensions());
+        [
+            dimensions(&self.base),
+            dimensions(&self.occlusion),
+            dimensions(&self.emissive),
+            dimensions(&self.metallic_roughness)
+        ]
+    }
+
+    /// The size the maps of `mat` are baked at, validated, or with `opts.resize`
+    /// or `opts.reconcile` reconciled.
+    ///
+    /// The occlusion map is sampled bilinearly at the output size, so without
+    /// resizing it only sets the size of a material with no other maps.
+    fn output_dimensions(&self, mat: &Material, opts: &UnlitOptions) -> Result<(u32, u32), UnlitError> {
+        let dimensions = self.dimensions();
+        let [base, occlusion, emissive, metallic_roughness] = dimensions;
+        let maps = [base, emissive, metallic_roughness];
+        let result = match (opts.resize, opts.reconcile) {
+            (Some(_), _) => largest_dimensions(dimensions.iter().filter_map(|&d| d)),
+            (None, Some(Reconcile::Crop)) => maps.iter().filter_map(|&d| d).next().ok_or(UnlitError::MissingTexture),
+            (None, Some(Reconcile::Pad)) => maps.iter().filter_map(|&d| d)
+                .fold(None, |largest, (w, h)| Some(largest.map_or((w, h), |(lw, lh): (u32, u32)| (lw.max(w), lh.max(h)))))
+                .ok_or(UnlitError::MissingTexture),
+            (None, None) => validate_dimensions(maps.iter().filter_map(|&d| d))
+        };
+        match (result, occlusion, opts.default_size) {
+            (Err(UnlitError::MissingTexture), Some(occlusion), _) => Ok(occlusion),
+            (Err(UnlitError::MissingTexture), None, Some(size)) => {
+                if !opts.solid {
+                    warn!("Material {} has no maps, using its base color factor at {}x{}.",
+                        mat.name().unwrap_or("(unnamed)"), size, size);
+                }
+                Ok((size, size))
+            },
+            (result, _, _) => result
+        }
+    }
+}
+
+fn load_source_maps<M: SourceMap>(mat: &Material, mat_json: &JsonValue, textures: &TextureCache, opts: &UnlitOptions) -> Result<SourceMaps<M>, UnlitError> {
+    textures.check_references(mat_json)?;
+    if opts.solid {
+        return Ok(SourceMaps { base: None, occlusion: None, emissive: None, metallic_roughness: None, occlusion_uv: None });
+    }
+    let load = |texture: Texture| M::load(textures, &texture).map(|map| (map, texture_wrap_modes(&texture)));
+    let pbr = mat.pbr_metallic_roughness();
+    // The gltf crate does not deserialize specular glossiness, so its diffuse
+    // texture is looked up by index
+    let base = match specular_glossiness(mat_json) {
+        Some(_) => base_color_texture_json(mat_json)["index"].as_u64().and_then(|index| M::load_index(textures, index as usize)),
+        None => pbr.base_color_texture().and_then(|info| load(info.texture()))
+    };
+
+    // The base color of an already unlit material is its final color, so occlusion
+    // and emissive are not baked on top of it
+    let unlit = is_unlit(mat_json);
+    let occlusion = if unlit { None } else { mat.occlusion_texture().and_then(|info| load(info.texture())) };
+    let emissive = if unlit { None } else { mat.emissive_texture().and_then(|info| load(info.texture())) };
+    // Specular glossiness materials have no metalness to darken
+    let metallic_roughness = if unlit || opts.metal_darken == 0.0 || specular_glossiness(mat_json).is_some() {
+        None
+    } else {
+        pbr.metallic_roughness_texture().and_then(|info| load(info.texture()))
+    };
+
+    let layout = |name, info_json: &JsonValue| (name, tex_coord(info_json), TextureTransform::from_info_json(info_json));
+    let layouts = [
+        base.as_ref().map(|_| layout("base color", base_color_texture_json(mat_json))),
+        emissive.as_ref().map(|_| layout("emissive", &mat_json["emissiveTexture"])),
+        metallic_roughness.as_ref().map(|_| layout("metallic roughness", &mat_json["pbrMetallicRoughness"]["metallicRoughnessTexture"]))
+    ];
+    validate_uv_layouts(layouts.iter().filter_map(|&l| l))?;
+
+    // Occlusion is sampled at its own resolution anyway, so it only has to share
+    // the texCoord set of the other maps and may have its own transform
+    let reference = layouts.iter().filter_map(|&l| l).next();
+    let occlusion_uv = match (&occlusion, reference) {
+        (&Some((_, wrap)), Some((reference, reference_tex_coord, reference_transform))) => {
+            let (map, tex_coord, transform) = layout("occlusion", &mat_json["occlusionTexture"]);
+            if tex_coord != reference_tex_coord {
+                return Err(UnlitError::TexCoordMismatch { map, tex_coord, reference, reference_tex_coord });
+            }
+            if transform == reference_transform {
+                None
+            } else {
+                debug!("Sampling the occlusion map of {} through its own texture transform", mat.name().unwrap_or("(unnamed)"));
+                Some((UvTransform::between(&reference_transform, &transform), wrap))
+            }
+        },
+        _ => None
+    };
+
+    Ok(SourceMaps { base, occlusion, emissive, metallic_roughness, occlusion_uv })
+}
+
+/// Returns true if a map is the same decoded image, sampled with the same wrap
+/// modes, as `other`.
+fn shares_source(img: &Arc<DynamicImage>, wrap: WrapModes, other: &Option<(Arc<DynamicImage>, WrapModes)>) -> bool {
+    match *other {
+        Some((ref other_img, other_wrap)) => Arc::ptr_eq(img, other_img)
+            && mem::discriminant(&wrap.0) == mem::discriminant(&other_wrap.0)
+            && mem::discriminant(&wrap.1) == mem::discriminant(&other_wrap.1),
+        None => false
+    }
+}
+
+/// Resamples a map to `w` by `h` when resizing is enabled, or centers it on a
+/// `w` by `h` image of `fill` when reconciling.
+fn fit_to<P>(img: ImageBuffer<P, Vec<u8>>, w: u32, h: u32, opts: &UnlitOptions, wrap: WrapModes, fill: P) -> ImageBuffer<P, Vec<u8>>
+    where P: Pixel<Subpixel=u8> + 'static
+{
+    match (opts.resize, opts.reconcile) {
+        (Some(filter), _) => resize_to(img, w, h, filter, wrap),
+        (None, Some(_)) if img.dimensions() != (w, h) => center_on(&img, w, h, fill),
+        _ => img
+    }
+}
+
+/// Centers `img` on a `w` by `h` image filled with `fill`, cropping whatever
+/// overhangs it.
+pub fn center_on<P>(img: &ImageBuffer<P, Vec<u8>>, w: u32, h: u32, fill: P) -> ImageBuffer<P, Vec<u8>>
+    where P: Pixel<Subpixel=u8> + 'static
+{
+    let (img_w, img_h) = img.dimensions();
+    let dx = (w as i64 - img_w as i64) / 2;
+    let dy = (h as i64 - img_h as i64) / 2;
+    ImageBuffer::from_fn(w, h, |x, y| {
+        let (sx, sy) = (x as i64 - dx, y as i64 - dy);
+        if sx >= 0 && sy >= 0 && sx < img_w as i64 && sy < img_h as i64 {
+            *img.get_pixel(sx as u32, sy as u32)
+        } else {
+            fill
+        }
+    })
+}
+
+/// What baking a material would use: the dimensions of each map that was found
+/// and the size of the output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialPlan {
+    pub width: u32,
+    pub height: u32,
+    pub base_dimensions: Option<(u32, u32)>,
+    pub occlusion_dimensions: Option<(u32, u32)>,
+    pub emissive_dimensions: Option<(u32, u32)>,
+    /// Only loaded when `UnlitOptions::metal_darken` is enabled.
+    pub metallic_roughness_dimensions: Option<(u32, u32)>
+}
+
+/// Resolves the textures of a material and validates their dimensions like
+/// [`load_material_maps`], without converting or resampling any pixels. PNG and
+/// JPEG textures only have their headers read.
+pub fn plan_material(mat: &Material, mat_json: &JsonValue, textures: &TextureCache, opts: &UnlitOptions) -> Result<MaterialPlan, UnlitError> {
+    let source = load_source_maps::<(u32, u32)>(mat, mat_json, textures, opts)?;
+    let (width, height) = source.output_dimensions(mat, opts)?;
+    let (width, height) = final_dimensions(width, height, opts);
+    let [base_dimensions, occlusion_dimensions, emissive_dimensions, metallic_roughness_dimensions] = source.dimensions();
+    Ok(MaterialPlan { width, height, base_dimensions, occlusion_dimensions, emissive_dimensions, metallic_roughness_dimensions })
+}
+
+/// Loads the input maps of a material and validates, or with `opts.resize`
+/// reconciles, their dimensions.
+///
+/// `mat_json` is the material's raw JSON object, which is used to read extensions
+/// that the gltf crate does not deserialize. Textures are decoded through
+/// `textures`, which is shared by every material of the glTF.
+pub fn load_material_maps(mat: &Material, mat_json: &JsonValue, textures: &TextureCache, opts: &UnlitOptions) -> Result<MaterialMaps, UnlitError> {
+    let source = load_source_maps::<Arc<DynamicImage>>(mat, mat_json, textures, opts)?;
+    let (width, height) = source.output_dimensions(mat, opts)?;
+    // The extra occlusion map is shared by every material, so it is not resampled
+    // to fit each of them
+    let extra_occlusion_map = match opts.extra_occlusion {
+        Some(ref map) if !opts.solid && !is_unlit(mat_json) => {
+            if map.dimensions() != (width, height) {
+                return Err(UnlitError::DimensionMismatch { expected: (width, height), found: map.dimensions() });
+            }
+            Some(map.clone())
+        },
+        _ => None
+    };
+    info!("Baking material {} at {}x{}", mat.name().unwrap_or("(unnamed)"), width, height);
+    // Unlit materials are not lit, so there is nothing for a normal map to shade
+    if mat.normal_texture().is_some() {
+        info!("Material {} has a normal map, which is intentionally not baked into unlit textures", mat.name().unwrap_or("(unnamed)"));
+    }
+
+    let pbr = mat.pbr_metallic_roughness();
+    let base_color_factor = opts.base_color_factor.unwrap_or_else(|| base_color_factor(mat, mat_json));
+    let base_map = source.base.map(|(img, wrap)| fit_to(img.to_rgba(), width, height, opts, wrap, Rgba([0, 0, 0, 0])));
+
+    let occlusion_strength = mat.occlusion_texture().map_or(0.0, |t| opts.occlusion_strength.map_or(t.strength(), |s| s.clamp(0.0, 1.0)));
+    let occlusion_source = source.occlusion.as_ref().map(|&(ref img, wrap)| (img.clone(), wrap));
+    // Occlusion is sampled bilinearly when baking instead of being resampled here
+    let occlusion_map = source.occlusion.map(|(img, _)| {
+        let mut occlusion_map = img.to_rgb();
+        select_occlusion_channel(&mut occlusion_map, opts.occlusion_channel);
+        occlusion_map
+    });
+
+    let emissive_map = source.emissive.map(|(img, wrap)| fit_to(img.to_rgb(), width, height, opts, wrap, Rgb([0, 0, 0])));
+    // Unlit and --solid output is only the base color, and an emissive texture that
+    // failed to load must not turn its factor into a flat wash over the material
+    let emissive_factor = if is_unlit(mat_json) || opts.solid || (emissive_map.is_none() && mat.emissive_texture().is_some()) {
+        [0.0; 3]
+    } else {
+        let emissive_strength = emissive_strength(mat_json);
+        let emissive_factor = mat.emissive_factor();
+        [
+            emissive_factor[0] * emissive_strength,
+            emissive_factor[1] * emissive_strength,
+            emissive_factor[2] * emissive_strength
+        ]
+    };
+
+    let metallic_factor = if specular_glossiness(mat_json).is_some() { 0.0 } else { pbr.metallic_factor() };
+    let roughness_factor = pbr.roughness_factor();
+    // A packed ORM texture is shared by occlusion and metallic roughness, so the
+    // converted occlusion map is reused instead of converting the image again
+    let metallic_roughness_map = match (source.metallic_roughness, &occlusion_map) {
+        (Some((ref img, wrap)), Some(occlusion_map)) if shares_source(img, wrap, &occlusion_source) => {
+            debug!("Material {} packs occlusion and metallic roughness into one texture", mat.name().unwrap_or("(unnamed)"));
+            Some(fit_to(occlusion_map.clone(), width, height, opts, wrap, Rgb([0, 0, 0])))
+        },
+        (Some((img, wrap)), _) => Some(fit_to(img.to_rgb(), width, height, opts, wrap, Rgb([0, 0, 0]))),
+        (None, _) => None
+    };
+
+    let alpha_cutoff = match mat.alpha_mode() {
+        AlphaMode::Mask => Some(mat.alpha_cutoff()),
+        _ => None
+    };
+
+    if opts.sanity_check {
+        // An ORM texture is colored by its roughness and metalness channels
+        let occlusion_index = &mat_json["occlusionTexture"]["index"];
+        let packed = !occlusion_index.is_null() && *occlusion_index == mat_json["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"];
+        for warning in swapped_map_warnings(occlusion_map.as_ref(), emissive_map.as_ref(), emissive_factor, packed) {
+            warn!("Material {}: {} Check that its occlusion and emissive maps are not swapped.", mat.name().unwrap_or("(unnamed)"), warning);
+        }
+    }
+
+    Ok(MaterialMaps {
+        width,
+        height,
+        base_color_factor,
+        base_map,
+        occlusion_strength,
+        occlusion_map,
+        occlusion_uv: source.occlusion_uv,
+        extra_occlusion_map,
+        emissive_factor,
+        emissive_map,
+        metallic_factor,
+        roughness_factor,
+        metallic_roughness_map,
+        alpha_cutoff
+    })
+}
+
+/// The mean spread between the largest and smallest channel of each texel, and
+/// the mean brightness, of an RGB map. Both are 0.0 - 255.0.
+fn color_stats(map: &RgbImage) -> (f32, f32) {
+    let (mut spread, mut brightness) = (0u64, 0u64);
+    for pixel in map.pixels() {
+        let [r, g, b] = pixel.data;
+        spread += (r.max(g).max(b) - r.min(g).min(b)) as u64;
+        brightness += (r as u64 + g as u64 + b as u64) / 3;
+    }
+    let count = (map.width() as u64 * map.height() as u64).max(1) as f32;
+    (spread as f32 / count, brightness as f32 / count)
+}
+
+/// Heuristics for an occlusion map in the emissive slot or the other way around.
+/// Occlusion maps are mostly bright and gray while emissive maps are mostly dark
+/// or colored. `packed` occlusion maps share an ORM texture and are not checked.
+fn swapped_map_warnings(occlusion_map: Option<&RgbImage>, emissive_map: Option<&RgbImage>, emissive_factor: [f32; 3], packed: bool) -> Vec<&'static str> {
+    const GRAY_SPREAD: f32 = 4.0;
+    const COLORED_SPREAD: f32 = 48.0;
+    let mut warnings = vec![];
+    let untinted = emissive_factor[0] == emissive_factor[1] && emissive_factor[1] == emissive_factor[2];
+    if let Some(map) = emissive_map {
+        let (spread, brightness) = color_stats(map);
+        if untinted && spread < GRAY_SPREAD && brightness > 127.0 {
+            warnings.push("the emissive map is mostly bright and gray, like an occlusion map.");
+        }
+    }
+    if let Some(map) = occlusion_map {
+        if !packed && color_stats(map).0 > COLORED_SPREAD {
+            warnings.push("the occlusion map is strongly colored, unlike an occlusion map.");
+        }
+    }
+    warnings
+}
+
+/// Multiplies an 8-bit alpha value by `factor`. With an alpha cutoff the result is
+/// thresholded to 0 or 255 instead.
+fn scale_alpha(alpha: u8, factor: f32, cutoff: Option<f32>) -> u8 {
+    let alpha = alpha as f32 * factor;
+    match cutoff {
+        Some(cutoff) => if alpha >= cutoff * 255.0 { 255 } else { 0 },
+        None => alpha as u8
+    }
+}
+
+/// Composites the maps of a material into an 8-bit unlit texture.
+pub fn bake(maps: MaterialMaps, opts: &UnlitOptions) -> RgbaImage {
+    // In linear mode the maps are decoded from sRGB and composited in linear light
+    if opts.linear {
+        let linear_map = bake_linear(maps, opts);
+        // Quantizing the linear bake directly skips a lossy round trip through sRGB
+        return match (opts.output_linear, opts.dither) {
+            (true, false) => encode_linear(&linear_map),
+            (true, true) => encode_linear_dithered(&linear_map),
+            (false, false) => encode_srgb(&linear_map),
+            (false, true) => encode_srgb_dithered(&linear_map)
+        };
+    }
+
+    let mut unlit_map = bake_srgb(maps, opts);
+    if opts.output_linear {
+        decode_srgb(&mut unlit_map);
+    }
+    unlit_map
+}
+
+/// Composites the maps of a material directly on their 8-bit sRGB values.
+fn bake_srgb(maps: MaterialMaps, opts: &UnlitOptions) -> RgbaImage {
+    let MaterialMaps {
+        width: w, height: h, base_color_factor, base_map, occlusion_strength, occlusion_map, occlusion_uv, extra_occlusion_map,
+        emissive_factor, emissive_map, metallic_factor, roughness_factor, metallic_roughness_map, alpha_cutoff
+    } = maps;
+    let lighten = [
+        (opts.lighten_factor[0] * 255.0) as u8,
+        (opts.lighten_factor[1] * 255.0) as u8,
+        (opts.lighten_factor[2] * 255.0) as u8
+    ];
+
+    // Exposure and gamma are applied in linear light, which the default values skip
+    let toned = opts.exposure != 1.0 || opts.gamma != 1.0;
+    let tone_srgb = |value: u8| if toned {
+        linear_to_srgb(tone(srgb_to_linear(value), opts.exposure, opts.gamma))
+    } else {
+        value
+    };
+
+    // Without an emissive map the emissive factor is emitted everywhere
+    let flat_emissive = emissive_map.is_none() && emissive_factor != [0.0; 3];
+
+    // A base color map with nothing to composite onto it is already unlit
+    let identity = base_color_factor == [1.0; 4] && lighten == [0; 3] && !toned && alpha_cutoff.is_none()
+        && opts.metal_darken == 0.0 && occlusion_map.is_none() && extra_occlusion_map.is_none() && emissive_map.is_none() && !flat_emissive;
+    if identity {
+        if let Some(base_map) = base_map {
+            return base_map;
+        }
+    }
+
+    // Set the unlit_map to the base color map if it exists
+    let mut unlit_map = base_map.map_or_else(|| {
+        let mut monocolor = generate_monocolor(w, h, base_color_factor);
+        let alpha = scale_alpha(255, base_color_factor[3], alpha_cutoff);
+        for pixel in monocolor.pixels_mut() {
+            pixel.data[0] = tone_srgb(pixel.data[0]);
+            pixel.data[1] = tone_srgb(pixel.data[1]);
+            pixel.data[2] = tone_srgb(pixel.data[2]);
+            pixel.data[3] = alpha;
+        }
+        monocolor
+    }, |mut base_map| {
+        for pixel in base_map.pixels_mut() {
+            pixel.data[0] = tone_srgb((pixel.data[0] as f32 * base_color_factor[0]) as u8).saturating_add(lighten[0]);
+            pixel.data[1] = tone_srgb((pixel.data[1] as f32 * base_color_factor[1]) as u8).saturating_add(lighten[1]);
+            pixel.data[2] = tone_srgb((pixel.data[2] as f32 * base_color_factor[2]) as u8).saturating_add(lighten[2]);
+            pixel.data[3] = scale_alpha(pixel.data[3], base_color_factor[3], alpha_cutoff);
+        }
+        base_map
+    });
+
+    for_each_tile(&mut unlit_map, opts.tile_height, |tile, y0| {
+        if opts.metal_darken > 0.0 {
+            darken_metal_rows(tile, y0, metallic_roughness_map.as_ref(), metallic_factor, roughness_factor, opts.metal_darken);
+        }
+
+        // Multiply the occlusion map if it exists
+        if let Some(ref occlusion_map) = occlusion_map {
+            occlude_rows(tile, y0, h, occlusion_map, occlusion_uv.as_ref(), OcclusionParams::new(occlusion_strength, opts));
+        };
+        if let Some(ref extra_occlusion_map) = extra_occlusion_map {
+            occlude_rows(tile, y0, h, extra_occlusion_map, None, OcclusionParams::new(1.0, opts));
+        };
+
+        // Add the emissive map if it exists
+        if let Some(ref emissive_map) = emissive_map {
+            add_emissive_rows(tile, y0, emissive_map, emissive_factor);
+        } else if flat_emissive {
+            add_flat_emissive(tile, emissive_factor);
+        };
+    });
+
+    unlit_map
+}
+
+/// Composites the maps of a material in linear light without clamping, so
+/// emissive contributions above 1.0 are preserved.
+pub fn bake_linear(maps: MaterialMaps, opts: &UnlitOptions) -> LinearImage {
+    let MaterialMaps {
+        width: w, height: h, base_color_factor, base_map, occlusion_strength, occlusion_map, occlusion_uv, extra_occlusion_map,
+        emissive_factor, emissive_map, metallic_factor, roughness_factor, metallic_roughness_map, alpha_cutoff
+    } = maps;
+
+    let mut linear_map = base_map.map_or_else(
+        || LinearImage::from_pixel(w, h, Rgba::<f32>::from_channels(
+            tone(base_color_factor[0], opts.exposure, opts.gamma),
+            tone(base_color_factor[1], opts.exposure, opts.gamma),
+            tone(base_color_factor[2], opts.exposure, opts.gamma),
+            base_color_factor[3]
+        )),
+        |base_map| linear_base_color(&base_map, base_color_factor, opts.exposure, opts.gamma, opts.lighten_factor));
+
+    if let Some(cutoff) = alpha_cutoff {
+        for pixel in linear_map.pixels_mut() {
+            pixel.data[3] = if pixel.data[3] >= cutoff { 1.0 } else { 0.0 };
+        }
+    }
+
+    for_each_tile(&mut linear_map, opts.tile_height, |tile, y0| {
+        if opts.metal_darken > 0.0 {
+            darken_metal_rows_linear(tile, y0, metallic_roughness_map.as_ref(), metallic_factor, roughness_factor, opts.metal_darken);
+        }
+
+        if let Some(ref occlusion_map) = occlusion_map {
+            occlude_rows_linear(tile, y0, h, occlusion_map, occlusion_uv.as_ref(), OcclusionParams::new(occlusion_strength, opts));
+        };
+        if let Some(ref extra_occlusion_map) = extra_occlusion_map {
+            occlude_rows_linear(tile, y0, h, extra_occlusion_map, None, OcclusionParams::new(1.0, opts));
+        };
+
+        if let Some(ref emissive_map) = emissive_map {
+            add_emissive_rows_linear(tile, y0, emissive_map, emissive_factor);
+        } else if emissive_factor != [0.0; 3] {
+            add_flat_emissive_linear(tile, emissive_factor);
+        };
+    });
+
+    linear_map
+}
+
+/// Returns true for OPAQUE materials, whose alpha is ignored.
+pub fn is_opaque(mat: &Material) -> bool {
+    // The gltf crate's AlphaMode does not implement PartialEq
+    matches!(mat.alpha_mode(), AlphaMode::Opaque)
+}
+
+/// Generates the unlit texture for a material. See [`load_material_maps`] for
+/// how the inputs are resolved.
+///
+/// The base color factor is applied first, then occlusion, then emissive. The
+/// result is premultiplied by its alpha if enabled, and only then scaled and
+/// flipped, so occlusion always darkens the straight colors.
+pub fn generate_unlit(mat: &Material, mat_json: &JsonValue, textures: &TextureCache, opts: &UnlitOptions) -> Result<RgbaImage, UnlitError> {
+    let maps = load_material_maps(mat, mat_json, textures, opts)?;
+    let expected = final_dimensions(maps.width, maps.height, opts);
+    let mut unlit_map = bake(maps, opts);
+
+    // Premultiplied with the final alpha, after the base color factor, occlusion, and emissive,
+    // since premultiplying first would let emissive add to texels alpha has already darkened
+    if opts.premultiply && !is_opaque(mat) {
+        premultiply_alpha(&mut unlit_map);
+    }
+    let unlit_map = finish_texture(unlit_map, opts);
+    validate_output(&unlit_map, expected)?;
+    Ok(unlit_map)
+}
+
+/// Bakes a single contribution of a material's maps at the size of its unlit
+/// texture. Without an occlusion map the occlusion is white, and without any
+/// emissive the emissive is black. Both are opaque.
+pub fn bake_component(maps: MaterialMaps, component: Component, opts: &UnlitOptions) -> RgbaImage {
+    let (w, h) = (maps.width, maps.height);
+    match component {
+        Component::Base => bake(MaterialMaps { occlusion_map: None, extra_occlusion_map: None, emissive_factor: [0.0; 3], emissive_map: None, ..maps }, opts),
+        Component::Occlusion => {
+            let mut occlusion = RgbaImage::from_pixel(w, h, Rgba([255; 4]));
+            if let Some(ref occlusion_map) = maps.occlusion_map {
+                for_each_tile(&mut occlusion, opts.tile_height, |tile, y0| {
+                    occlude_rows(tile, y0, h, occlusion_map, maps.occlusion_uv.as_ref(), OcclusionParams::new(maps.occlusion_strength, opts));
+                });
+            }
+            if let Some(ref extra_occlusion_map) = maps.extra_occlusion_map {
+                for_each_tile(&mut occlusion, opts.tile_height, |tile, y0| {
+                    occlude_rows(tile, y0, h, extra_occlusion_map, None, OcclusionParams::new(1.0, opts));
+                });
+            }
+            occlusion
+        },
+        Component::Emissive => {
+            let mut emissive = RgbaImage::from_pixel(w, h, Rgba([0, 0, 0, 255]));
+            match maps.emissive_map {
+                Some(ref emissive_map) => for_each_tile(&mut emissive, opts.tile_height, |tile, y0| {
+                    add_emissive_rows(tile, y0, emissive_map, maps.emissive_factor);
+                }),
+                None => add_flat_emissive(&mut emissive, maps.emissive_factor)
+            }
+            if opts.output_linear {
+                decode_srgb(&mut emissive);
+            }
+            emissive
+        }
+    }
+}
+
+/// Generates one contribution to the unlit texture of a material, like
+/// [`generate_unlit`] but without compositing the others. The base color is
+/// premultiplied the same way.
+pub fn generate_component(mat: &Material, mat_json: &JsonValue, textures: &TextureCache, opts: &UnlitOptions, component: Component) -> Result<RgbaImage, UnlitError> {
+    let maps = load_material_maps(mat, mat_json, textures, opts)?;
+    let expected = final_dimensions(maps.width, maps.height, opts);
+    let mut img = bake_component(maps, component, opts);
+    if component == Component::Base && opts.premultiply && !is_opaque(mat) {
+        premultiply_alpha(&mut img);
+    }
+    let img = finish_texture(img, opts);
+    validate_output(&img, expected)?;
+    Ok(img)
+}
+
+/// Checks that a generated texture is `expected` pixels in size and has every
+/// texel's four channels, before it is handed to an encoder.
+pub fn validate_output(img: &RgbaImage, expected: (u32, u32)) -> Result<(), UnlitError> {
+    let (w, h) = img.dimensions();
+    if (w, h) != expected {
+        return Err(UnlitError::InvalidOutput(format!("It is {}x{} instead of {}x{}.", w, h, expected.0, expected.1)));
+    }
+    if img.len() != w as usize * h as usize * 4 {
+        return Err(UnlitError::InvalidOutput(format!("It has {} bytes instead of 4 channels of {}x{} texels.", img.len(), w, h)));
+    }
+    Ok(())
+}
+
+/// The format an unlit texture is best saved in: jpg for opaque materials and png,
+/// which keeps the alpha channel, for everything else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnlitFormat {
+    Png,
+    Jpeg
+}
+
+impl UnlitFormat {
+    pub fn for_material(mat: &Material) -> UnlitFormat {
+        match mat.alpha_mode() {
+            AlphaMode::Opaque => UnlitFormat::Jpeg,
+            _ => UnlitFormat::Png
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            UnlitFormat::Png => "png",
+            UnlitFormat::Jpeg => "jpg"
+        }
+    }
+}
+
+/// The unlit texture generated for a material by [`generate_all`].
+#[derive(Clone, Debug)]
+pub struct UnlitOutput {
+    pub image: RgbaImage,
+    /// The index of the material in the glTF.
+    pub material: usize,
+    pub name: Option<String>,
+    pub format: UnlitFormat
+}
+
+impl UnlitOutput {
+    /// Encodes the texture in its format, with JPEGs at `jpeg_quality`. Neither
+    /// encoder writes timestamps or other metadata, so the same texture always
+    /// encodes to the same bytes.
+    pub fn encode(&self, jpeg_quality: u8) -> Result<Vec<u8>, UnlitError> {
+        let (w, h) = self.image.dimensions();
+        let mut data = Vec::new();
+        match self.format {
+            UnlitFormat::Png => PNGEncoder::new(&mut data).encode(&self.image, w, h, ColorType::RGBA(8))?,
+            UnlitFormat::Jpeg => JPEGEncoder::new_with_quality(&mut data, jpeg_quality).encode(&self.image, w, h, ColorType::RGBA(8))?
+        }
+        Ok(data)
+    }
+}
+
+/// Generates the unlit texture of every material in memory, in material order.
+///
+/// Extensions the gltf crate does not deserialize are read from the raw
+/// `document` JSON. Nothing is read from disk except the external images and
+/// buffers `textures` was created to load.
+pub fn generate_all(gltf: &Gltf, document: &JsonValue, textures: &TextureCache, opts: &UnlitOptions) -> Vec<Result<UnlitOutput, UnlitError>> {
+    let materials = gltf.materials().collect::<Vec<_>>();
+    materials.par_iter().map(|mat| {
+        let material = mat.index().unwrap();
+        generate_unlit(mat, &document["materials"][material], textures, opts).map(|image| UnlitOutput {
+            image,
+            material,
+            name: mat.name().map(String::from),
+            format: UnlitFormat::for_material(mat)
+        })
+    }).collect()
+}
+
+/// Parses a .gltf or .glb from `gltf_bytes` and generates the unlit texture of
+/// every material like [`generate_all`], without touching the filesystem.
+/// External images and buffers are loaded from the bytes `resolve` returns for
+/// their uris.
+pub fn generate_all_from_bytes<F: FnMut(&str) -> Option<Vec<u8>>>(gltf_bytes: &[u8], mut resolve: F, opts: &UnlitOptions) -> Result<Vec<Result<UnlitOutput, UnlitError>>, UnlitError> {
+    let invalid = |e: &dyn Error| UnlitError::InvalidGltf(e.to_string());
+    // Binary glTF keeps its JSON and buffer data in separate chunks
+    let (gltf, document, blob) = if gltf::is_binary(gltf_bytes) {
+        let glb = Glb::from_slice(gltf_bytes).map_err(|e| invalid(&e))?;
+        let document = serde_json::from_slice(&glb.json).map_err(|e| invalid(&e))?;
+        let gltf = Gltf::from_glb(&glb).and_then(validate_gltf).map_err(|e| invalid(&e))?;
+        (gltf, document, glb.bin.map(|bin| bin.into_owned()))
+    } else {
+        let document = serde_json::from_slice(gltf_bytes).map_err(|e| invalid(&e))?;
+        let gltf = Gltf::from_slice(gltf_bytes).and_then(validate_gltf).map_err(|e| invalid(&e))?;
+        (gltf, document, None)
+    };
+    let buffers = resolve_buffers(&gltf, blob, &mut resolve);
+    let textures = TextureCache::new(&gltf, Path::new(""), buffers).with_resolver(&mut resolve);
+    Ok(generate_all(&gltf, &document, &textures, opts))
+}
+
+/// Generates a linear, unclamped HDR unlit texture for a material.
+pub fn generate_unlit_hdr(mat: &Material, mat_json: &JsonValue, textures: &TextureCache, opts: &UnlitOptions) -> Result<LinearImage, UnlitError> {
+    load_material_maps(mat, mat_json, textures, opts).map(|maps| {
+        let mut linear_map = bake_linear(maps, opts);
+        replace_non_finite(&mut linear_map);
+        finish_texture(linear_map, opts)
+    })
+}
+
+/// The largest half float, which every HDR format can represent.
+const HDR_MAX: f32 = 65504.0;
+
+/// Replaces NaN channels of an unclamped bake with 0.0 and clamps infinities
+/// to 0.0 - `HDR_MAX`, so that they are not spread by resampling or written out.
+/// Extreme factors, such as a huge emissive strength over a black texel, can
+/// produce them.
+pub fn replace_non_finite(img: &mut LinearImage) {
+    for value in img.iter_mut() {
+        if value.is_nan() {
+            *value = 0.0;
+        } else if value.is_infinite() {
+            *value = if *value > 0.0 { HDR_MAX } else { 0.0 };
+        }
+    }
+}
+
+/// The size of a `w` by `h` texture downscaled by `scale`, rounded up to at least
+/// one pixel.
+pub fn scaled_dimensions(w: u32, h: u32, scale: f32) -> (u32, u32) {
+    let scaled = |size: u32| ((size as f64 * scale as f64).ceil() as u32).max(1);
+    (scaled(w), scaled(h))
+}
+
+/// The power of two nearest to `size` in the direction of `mode`.
+pub fn power_of_two(size: u32, mode: PowerOfTwo) -> u32 {
+    match mode {
+        PowerOfTwo::Up => size.next_power_of_two(),
+        PowerOfTwo::Down if size == 0 => 1,
+        PowerOfTwo::Down => 1 << (31 - size.leading_zeros())
+    }
+}
+
+/// The size a `w` by `h` bake is written at, downscaled by `opts.scale`, rounded
+/// to a power of two, and then capped at `opts.max_dimension`.
+fn final_dimensions(w: u32, h: u32, opts: &UnlitOptions) -> (u32, u32) {
+    let (w, h) = uncapped_dimensions(w, h, opts);
+    match opts.max_dimension {
+        Some(max_dimension) => capped_dimensions(w, h, max_dimension),
+        None => (w, h)
+    }
+}
+
+/// [`final_dimensions`] before they are capped at `opts.max_dimension`.
+fn uncapped_dimensions(w: u32, h: u32, opts: &UnlitOptions) -> (u32, u32) {
+    let (w, h) = scaled_dimensions(w, h, opts.scale);
+    match opts.power_of_two {
+        Some(mode) => (power_of_two(w, mode), power_of_two(h, mode)),
+        None => (w, h)
+    }
+}
+
+/// The size of a `w` by `h` texture proportionally downscaled so neither side is
+/// larger than `max_dimension`, rounded to at least one pixel.
+pub fn capped_dimensions(w: u32, h: u32, max_dimension: u32) -> (u32, u32) {
+    let largest = w.max(h);
+    if largest <= max_dimension {
+        return (w, h);
+    }
+    let capped = |size: u32| ((size as u64 * max_dimension as u64 + largest as u64 / 2) / largest as u64).max(1) as u32;
+    (capped(w), capped(h))
+}
+
+/// Resizes a baked texture to its [`final_dimensions`] with a Lanczos filter, and
+/// flips it if `opts.flip_v` is set.
+fn finish_texture<P>(img: ImageBuffer<P, Vec<P::Subpixel>>, opts: &UnlitOptions) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where P: Pixel + 'static, P::Subpixel: 'static
+{
+    let (w, h) = img.dimensions();
+    let (final_w, final_h) = final_dimensions(w, h, opts);
+    let (uncapped_w, uncapped_h) = uncapped_dimensions(w, h, opts);
+    if (final_w, final_h) != (uncapped_w, uncapped_h) {
+        info!("Capped a {}x{} texture at {}x{}", uncapped_w, uncapped_h, final_w, final_h);
+    }
+    let img = if (final_w, final_h) == (w, h) {
+        img
+    } else {
+        imageops::resize(&img, final_w, final_h, FilterType::Lanczos3)
+    };
+    if opts.flip_v {
+        imageops::flip_vertical(&img)
+    } else {
+        img
+    }
+}
+
+/// Generates the mip chain of a texture, from `img` itself down to 1x1. Each level
+/// is half the size of the one above it, rounded down, and filtered from it.
+pub fn generate_mips(img: RgbaImage, filter: MipFilter) -> Vec<RgbaImage> {
+    let mut levels = vec![img];
+    loop {
+        let (w, h) = levels.last().unwrap().dimensions();
+        if w == 1 && h == 1 {
+            return levels;
+        }
+        let (mip_w, mip_h) = ((w / 2).max(1), (h / 2).max(1));
+        let mip = match filter {
+            MipFilter::Box => downsample_box(levels.last().unwrap(), mip_w, mip_h),
+            MipFilter::Resize(filter) => imageops::resize(levels.last().unwrap(), mip_w, mip_h, filter.filter_type())
+        };
+        levels.push(mip);
+    }
+}
+
+/// Averages the 2x2 block of `img` under each texel, repeating the last row or
+/// column of odd sizes.
+fn downsample_box(img: &RgbaImage, w: u32, h: u32) -> RgbaImage {
+    let (img_w, img_h) = img.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| {
+        let (x0, y0) = (2 * x, 2 * y);
+        let (x1, y1) = ((x0 + 1).min(img_w - 1), (y0 + 1).min(img_h - 1));
+        let mut sum = [0u32; 4];
+        for &(x, y) in &[(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+            for (sum, &value) in sum.iter_mut().zip(&img.get_pixel(x, y).data) {
+                *sum += value as u32;
+            }
+        }
+        Rgba([
+            ((sum[0] + 2) / 4) as u8,
+            ((sum[1] + 2) / 4) as u8,
+            ((sum[2] + 2) / 4) as u8,
+            ((sum[3] + 2) / 4) as u8
+        ])
+    })
+}
+
+/// Decodes the images of a glTF, each at most once, so that materials sharing a
+/// texture do not decode it again. It can be shared between threads.
+pub struct TextureCache {
+    dir: PathBuf,
+    buffers: Vec<Option<Vec<u8>>>,
+    /// One slot per glTF image, `None` until the image is first requested and
+    /// `Some(None)` if it failed to load.
+    images: Vec<Mutex<Option<Option<Arc<DynamicImage>>>>>,
+    /// Where each glTF image is loaded from.
+    sources: Vec<ImageSource>,
+    /// The source image index and wrap modes of each glTF texture, so textures
+    /// referenced only from extension JSON can be loaded by index.
+    textures: Vec<(usize, WrapModes)>
+}
+
+/// An owned copy of where a glTF image's data is stored.
+#[derive(Clone, Debug)]
+enum ImageSource {
+    Uri(String),
+    /// A file found in the search path in place of a missing uri.
+    File(PathBuf),
+    /// The contents of a uri, as returned by a [`TextureCache::with_resolver`].
+    Bytes { uri: String, data: Vec<u8> },
+    View(ViewSource)
+}
+
+/// The buffer view an image is embedded in.
+#[derive(Clone, Debug)]
+struct ViewSource {
+    index: usize,
+    buffer: usize,
+    buffer_uri: String,
+    offset: usize,
+    length: usize,
+    mime_type: String
+}
+
+impl ImageSource {
+    fn new(data: Data) -> ImageSource {
+        match data {
+            Data::Uri { uri, .. } => ImageSource::Uri(String::from(uri)),
+            Data::View { view, mime_type } => ImageSource::View(ViewSource {
+                index: view.index(),
+                buffer: view.buffer().index(),
+                buffer_uri: String::from(view.buffer().uri()),
+                offset: view.offset(),
+                length: view.length(),
+                mime_type: String::from(mime_type)
+            })
+        }
+    }
+}
+
+// DynamicImage does not implement Debug
+impl fmt::Debug for TextureCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TextureCache")
+            .field("dir", &self.dir)
+            .field("buffers", &self.buffers)
+            .field("images", &self.images.len())
+            .field("sources", &self.sources)
+            .field("textures", &self.textures)
+            .finish()
+    }
+}
+
+impl TextureCache {
+    /// Relative image URIs are resolved against `dir` and buffer view images are
+    /// sliced out of `buffers`, as returned by [`load_buffers`].
+    pub fn new(gltf: &Gltf, dir: &Path, buffers: Vec<Option<Vec<u8>>>) -> TextureCache {
+        TextureCache {
+            dir: dir.to_path_buf(),
+            buffers,
+            images: gltf.images().map(|_| Mutex::new(None)).collect(),
+            sources: gltf.images().map(|image| ImageSource::new(image.data())).collect(),
+            // Read from the JSON, as a source out of range makes `Texture::source` panic
+            textures: gltf.textures().map(|texture| (texture.as_json().source.value(), texture_wrap_modes(&texture))).collect()
+        }
+    }
+
+    /// Looks for the files of images missing from their uris in each of `dirs` in
+    /// turn, by file name, for assets that were moved without updating the glTF.
+    pub fn with_search_path(mut self, dirs: &[PathBuf]) -> TextureCache {
+        if dirs.is_empty() {
+            return self;
+        }
+        for (index, source) in self.sources.iter_mut().enumerate() {
+            let path = match *source {
+                ImageSource::Uri(ref uri) if !uri.starts_with("data:") => resolve_uri(&self.dir, uri),
+                _ => continue
+            };
+            if path.exists() {
+                continue;
+            }
+            let file_name = match path.file_name() {
+                Some(file_name) => file_name,
+                None => continue
+            };
+            let candidates = dirs.iter().map(|dir| dir.join(file_name)).collect::<Vec<_>>();
+            match candidates.iter().find(|candidate| candidate.is_file()) {
+                Some(found) => {
+                    info!("Image {} is missing from {}, using {} instead", index, path.display(), found.display());
+                    *source = ImageSource::File(found.clone());
+                },
+                None => {
+                    let looked = candidates.iter().map(|candidate| candidate.display().to_string()).collect::<Vec<_>>();
+                    warn!("Image {} is missing from {} and was not found at {}", index, path.display(), looked.join(", "));
+                }
+            }
+        }
+        self
+    }
+
+    /// Loads images from the bytes `resolve` returns for their uris instead of from
+    /// files, for callers without a filesystem. Images it returns `None` for are
+    /// still read from their files.
+    pub fn with_resolver<F: FnMut(&str) -> Option<Vec<u8>>>(mut self, mut resolve: F) -> TextureCache {
+        for (index, source) in self.sources.iter_mut().enumerate() {
+            let uri = match *source {
+                ImageSource::Uri(ref uri) if !uri.starts_with("data:") => uri.clone(),
+                _ => continue
+            };
+            match resolve(&uri) {
+                Some(data) => {
+                    debug!("Resolved image {} from {}", index, uri);
+                    *source = ImageSource::Bytes { uri, data };
+                },
+                None => warn!("Unable to resolve image {} from {}", index, uri)
+            }
+        }
+        self
+    }
+
+    /// Checks that every texture info in a material's JSON refers to a texture
+    /// and image that exist, since the gltf crate panics on references out of
+    /// range. They can only get this far when loaded with [`validate_gltf`].
+    pub fn check_references(&self, mat_json: &JsonValue) -> Result<(), UnlitError> {
+        let mut infos = Vec::new();
+        texture_infos(mat_json, &mut infos);
+        for (key, index) in infos {
+            let exists = match self.textures.get(index) {
+                Some(&(image, _)) => image < self.images.len(),
+                None => false
+            };
+            if !exists {
+                warn!("The {} of material {} refers to texture {}, which is missing or has no image.",
+                    key, mat_json["name"].as_str().unwrap_or("(unnamed)"), index);
+                return Err(UnlitError::MissingTexture);
+            }
+        }
+        Ok(())
+    }
+
+    /// The loaded buffers, indexed by buffer.
+    pub fn buffers(&self) -> &[Option<Vec<u8>>] {
+        &self.buffers
+    }
+
+    /// Returns the decoded source image of a texture, decoding it on first use.
+    pub fn load(&self, texture: &Texture) -> Option<Arc<DynamicImage>> {
+        let index = texture.source().index();
+        match self.images.get(index) {
+            Some(_) => self.load_image(index),
+            // The texture belongs to a different glTF than the cache
+            None => load_if_exists(&self.dir, &self.buffers, index, &ImageSource::new(texture.source().data())).map(Arc::new)
+        }
+    }
+
+    /// Returns the decoded source image and wrap modes of the texture at `index`,
+    /// for textures referenced from JSON the gltf crate does not deserialize.
+    pub fn load_index(&self, index: usize) -> Option<(Arc<DynamicImage>, WrapModes)> {
+        let (image, wrap) = *self.textures.get(index)?;
+        self.load_image(image).map(|img| (img, wrap))
+    }
+
+    /// Returns the dimensions of a texture's source image. PNGs and JPEGs that are
+    /// not decoded yet only have their header read, other images are decoded.
+    pub fn load_dimensions(&self, texture: &Texture) -> Option<(u32, u32)> {
+        let index = texture.source().index();
+        match self.images.get(index) {
+            Some(_) => self.image_dimensions(index),
+            None => self.load(texture).map(|img| img.dimensions())
+        }
+    }
+
+    /// [`TextureCache::load_dimensions`] of the texture at `index`.
+    pub fn load_index_dimensions(&self, index: usize) -> Option<((u32, u32), WrapModes)> {
+        let (image, wrap) = *self.textures.get(index)?;
+        self.image_dimensions(image).map(|dimensions| (dimensions, wrap))
+    }
+
+    fn image_dimensions(&self, index: usize) -> Option<(u32, u32)> {
+        match *self.images.get(index)?.lock().unwrap() {
+            Some(Some(ref img)) => return Some(img.dimensions()),
+            Some(None) => return None,
+            None => {}
+        }
+        match read_dimensions(&self.dir, &self.buffers, &self.sources[index]) {
+            Ok(dimensions) => {
+                debug!("Read the dimensions of image {} from its header", index);
+                Some(dimensions)
+            },
+            Err(e) => {
+                debug!("Decoding image {} for its dimensions: {}", index, e);
+                self.load_image(index).map(|img| img.dimensions())
+            }
+        }
+    }
+
+    fn load_image(&self, index: usize) -> Option<Arc<DynamicImage>> {
+        // Holding the slot's lock while decoding keeps other threads from
+        // decoding the same image, without blocking unrelated images
+        let mut slot = self.images.get(index)?.lock().unwrap();
+        slot.get_or_insert_with(|| load_if_exists(&self.dir, &self.buffers, index, &self.sources[index]).map(Arc::new)).clone()
+    }
+}
+
+/// Collects the key and texture index of every texture info in `json`, which are
+/// the objects with an index under keys ending in `Texture`.
+fn texture_infos<'a>(json: &'a JsonValue, infos: &mut Vec<(&'a str, usize)>) {
+    if let Some(object) = json.as_object() {
+        for (key, value) in object {
+            match value["index"].as_u64() {
+                Some(index) if key.ends_with("Texture") => infos.push((key, index as usize)),
+                _ => texture_infos(value, infos)
+            }
+        }
+    }
+}
+
+/// Validates a glTF like `validate_minimally`, except that materials may refer
+/// to textures, and textures to images, that do not exist. Generating those
+/// materials fails with [`UnlitError::MissingTexture`] rather than failing the
+/// whole glTF.
+pub fn validate_gltf(gltf: Unvalidated) -> Result<Gltf, gltf::Error> {
+    let mut errors = Vec::new();
+    {
+        let json = gltf.as_json();
+        json.validate_minimally(json, gltf::json::Path::new, &mut |path, error| errors.push((path(), error)));
+    }
+    errors.retain(|(path, error)| {
+        let path = path.as_str();
+        let texture_reference = path.starts_with("materials[") || (path.starts_with("textures[") && path.ends_with(".source"));
+        !(texture_reference && *error == ValidationError::IndexOutOfBounds)
+    });
+    if errors.is_empty() {
+        Ok(gltf.skip_validation())
+    } else {
+        Err(gltf::Error::Validation(errors))
+    }
+}
+
+fn texture_wrap_modes(texture: &Texture) -> WrapModes {
+    let sampler = texture.sampler();
+    (sampler.wrap_s(), sampler.wrap_t())
+}
+
+/// The path of a percent-encoded file uri. Relative uris resolve from `dir`,
+/// while absolute paths and `file://` uris are used as they are.
+fn resolve_uri(dir: &Path, uri: &str) -> PathBuf {
+    let uri = percent_decode(uri);
+    let path = uri.strip_prefix("file://").unwrap_or(&uri);
+    if Path::new(path).is_absolute() {
+        PathBuf::from(path)
+    } else {
+        dir.join(path)
+    }
+}
+
+/// Decodes the `%XX` escapes of a uri, leaving it as it is if they do not decode
+/// to UTF-8.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            },
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| String::from(uri))
+}
+
+fn load_if_exists(dir: &Path, buffers: &[Option<Vec<u8>>], index: usize, source: &ImageSource) -> Option<DynamicImage> {
+    let load_result = match *source {
+        ImageSource::Uri(ref uri) if uri.starts_with("data:") => {
+            debug!("Decoding image {} from a data URI", index);
+            load_from_data_uri(uri)
+        },
+        ImageSource::Uri(ref uri) => load_from_file(index, &resolve_uri(dir, uri)),
+        ImageSource::File(ref path) => load_from_file(index, path),
+        ImageSource::Bytes { ref uri, ref data } => {
+            debug!("Decoding image {} from the bytes of {}", index, uri);
+            if ktx::has_ktx2_extension(Path::new(uri)) {
+                ktx::decode_ktx2(data)
+            } else {
+                decode_with_mime(data, "")
+            }
+        },
+        ImageSource::View(ref view) => {
+            debug!("Decoding image {} from buffer view {}", index, view.index);
+            load_from_view(view, buffers)
+        }
+    };
+    match load_result {
+        Ok(img) => {
+            let (w, h) = img.dimensions();
+            debug!("Decoded image {} at {}x{}", index, w, h);
+            Some(img)
+        },
+        Err(e) => {
+            warn!("Unable to load image {}: {}", index, e);
+            None
+        }
+    }
+}
+
+fn load_from_file(index: usize, path: &Path) -> Result<DynamicImage, UnlitError> {
+    debug!("Decoding image {} from {}", index, path.display());
+    if ktx::has_ktx2_extension(path) {
+        fs::read(path).map_err(UnlitError::from).and_then(|data| ktx::decode_ktx2(&data))
+    } else {
+        // Read into memory first so that 16-bit PNGs can be detected
+        fs::read(path).map_err(UnlitError::from).and_then(|data| decode_with_mime(&data, ""))
+    }
+}
+
+/// Loads every buffer that holds image data, indexed by buffer, from its file or
+/// data URI.
+///
+/// `blob` is the binary chunk of a .glb file, if any, and is always kept since it
+/// is already in memory. Other buffers that are not referenced by an image, or
+/// that fail to load, are `None`.
+pub fn load_buffers(gltf: &Gltf, dir: &Path, blob: Option<Vec<u8>>) -> Vec<Option<Vec<u8>>> {
+    read_buffers(gltf, blob, |uri| fs::read(resolve_uri(dir, uri)).map_err(UnlitError::from))
+}
+
+/// Loads buffers like [`load_buffers`], but from the bytes `resolve` returns for
+/// their uris instead of from files.
+pub fn resolve_buffers<F: FnMut(&str) -> Option<Vec<u8>>>(gltf: &Gltf, blob: Option<Vec<u8>>, mut resolve: F) -> Vec<Option<Vec<u8>>> {
+    read_buffers(gltf, blob, |uri| resolve(uri).ok_or_else(|| UnlitError::Io(io::Error::new(io::ErrorKind::NotFound, "not resolved"))))
+}
+
+fn read_buffers<F: FnMut(&str) -> Result<Vec<u8>, UnlitError>>(gltf: &Gltf, mut blob: Option<Vec<u8>>, mut read: F) -> Vec<Option<Vec<u8>>> {
+    // Only buffers holding image data are needed, geometry-only buffers are skipped
+    let image_buffers = gltf.images().filter_map(|image| match image.data() {
+        Data::View { view, .. } => Some(view.buffer().index()),
+        Data::Uri { .. } => None
+    }).collect::<Vec<_>>();
+
+    gltf.buffers().map(|buffer| {
+        // The binary chunk of a .glb file is the buffer without a uri
+        if buffer.uri() == "#bin" {
+            return blob.take();
+        }
+        if !image_buffers.contains(&buffer.index()) {
+            return None;
+        }
+        // Buffers embedded in a .gltf are data URIs
+        let loaded = if buffer.uri().starts_with("data:") {
+            decode_data_uri(buffer.uri()).map(|(_, data)| data)
+        } else {
+            read(buffer.uri())
+        };
+        match loaded {
+            Ok(data) => {
+                debug!("Loaded buffer {} from {}", buffer.index(), buffer.uri());
+                Some(data)
+            },
+            Err(e) => {
+                warn!("Unable to load buffer {}: {}", buffer.uri(), e);
+                None
+            }
+        }
+    }).collect()
+}
+
+/// The files the images of a glTF are loaded from, including the buffers of
+/// images embedded in buffer views. Data URIs and the binary chunk of a .glb are
+/// not files and are left out.
+pub fn referenced_files(gltf: &Gltf, dir: &Path) -> Vec<PathBuf> {
+    let mut uris = Vec::new();
+    for image in gltf.images() {
+        match image.data() {
+            Data::Uri { uri, .. } => uris.push(String::from(uri)),
+            Data::View { view, .. } => uris.push(String::from(view.buffer().uri()))
+        }
+    }
+    let mut files = uris.iter()
+        .filter(|uri| !uri.starts_with("data:") && *uri != "#bin")
+        .map(|uri| resolve_uri(dir, uri))
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn load_from_view(view: &ViewSource, buffers: &[Option<Vec<u8>>]) -> Result<DynamicImage, UnlitError> {
+    decode_with_mime(view_data(view, buffers)?, &view.mime_type)
+}
+
+/// The bytes of a buffer view image.
+fn view_data<'a>(view: &ViewSource, buffers: &'a [Option<Vec<u8>>]) -> Result<&'a [u8], UnlitError> {
+    let data = buffers.get(view.buffer).and_then(|data| data.as_ref())
+        .ok_or_else(|| UnlitError::UnsupportedDataView(format!("Buffer {} is not loaded.", view.buffer_uri)))?;
+    data.get(view.offset..view.offset + view.length)
+        .ok_or_else(|| UnlitError::UnsupportedDataView(format!("Buffer view {} is out of bounds.", view.index)))
+}
+
+/// Reads the dimensions of a PNG or JPEG image from its header without decoding
+/// its pixels.
+fn read_dimensions(dir: &Path, buffers: &[Option<Vec<u8>>], source: &ImageSource) -> Result<(u32, u32), UnlitError> {
+    match *source {
+        ImageSource::Uri(ref uri) if uri.starts_with("data:") => decode_data_uri(uri).and_then(|(_, data)| header_dimensions(&data[..])),
+        ImageSource::Uri(ref uri) => header_dimensions(BufReader::new(File::open(resolve_uri(dir, uri))?)),
+        ImageSource::File(ref path) => header_dimensions(BufReader::new(File::open(path)?)),
+        ImageSource::Bytes { ref data, .. } => header_dimensions(&data[..]),
+        ImageSource::View(ref view) => header_dimensions(view_data(view, buffers)?)
+    }
+}
+
+fn header_dimensions<R: BufRead>(mut reader: R) -> Result<(u32, u32), UnlitError> {
+    let dimensions = match image::guess_format(reader.fill_buf()?)? {
+        ImageFormat::PNG => PNGDecoder::new(reader).dimensions()?,
+        ImageFormat::JPEG => JPEGDecoder::new(reader).dimensions()?,
+        format => return Err(UnlitError::ImageDecode(ImageError::UnsupportedError(format!("{:?} headers are not read", format))))
+    };
+    Ok(dimensions)
+}
+
+fn load_from_data_uri(uri: &str) -> Result<DynamicImage, UnlitError> {
+    let (mime_type, data) = decode_data_uri(uri)?;
+    decode_with_mime(&data, mime_type)
+}
+
+/// Splits a base64 data URI into its mime type and decoded payload.
+fn decode_data_uri(uri: &str) -> Result<(&str, Vec<u8>), UnlitError> {
+    // data:[<mime type>][;base64],<payload>
+    let comma = uri.find(',').ok_or_else(|| UnlitError::UnsupportedDataUri(String::from("Malformed data URI.")))?;
+    let mut params = uri["data:".len()..comma].split(';');
+    let mime_type = params.next().unwrap_or("");
+    if !params.any(|param| param == "base64") {
+        return Err(UnlitError::UnsupportedDataUri(String::from("Only base64 encoded data URIs are supported.")));
+    }
+    let data = base64::decode(&uri[comma + 1..])
+        .map_err(|e| UnlitError::UnsupportedDataUri(format!("Invalid base64 data URI: {}", e)))?;
+    Ok((mime_type, data))
+}
+
+fn decode_with_mime(data: &[u8], mime_type: &str) -> Result<DynamicImage, UnlitError> {
+    // The image crate cannot decode KHR_texture_basisu images
+    if mime_type == "image/ktx2" || ktx::is_ktx2(data) {
+        return ktx::decode_ktx2(data);
+    }
+    if let Some(format) = gpu_texture_format(data) {
+        return Err(UnlitError::CompressedTexture(format));
+    }
+    if png16::is_png16(data) {
+        return png16::decode_png16(data);
+    }
+    let img = match image_format_from_mime(mime_type) {
+        Some(format) => image::load_from_memory_with_format(data, format)?,
+        None => image::load_from_memory(data)?
+    };
+    Ok(img)
+}
+
+/// Names the container of a GPU compressed texture, such as ETC2 or ASTC, from its
+/// magic bytes. KTX2 is handled separately by the `ktx2` feature.
+fn gpu_texture_format(data: &[u8]) -> Option<&'static str> {
+    const CONTAINERS: [(&str, &[u8]); 4] = [
+        ("KTX", &[0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A]),
+        ("DDS", b"DDS "),
+        ("PVR", b"PVR\x03"),
+        ("ASTC", &[0x13, 0xAB, 0xA1, 0x5C])
+    ];
+    CONTAINERS.iter().find(|&&(_, magic)| data.starts_with(magic)).map(|&(name, _)| name)
+}
+
+fn image_format_from_mime(mime_type: &str) -> Option<ImageFormat> {
+    match mime_type {
+        "image/png" => Some(ImageFormat::PNG),
+        "image/jpeg" => Some(ImageFormat::JPEG),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2 RGBA PNG: red, green / blue, half transparent white
+    const PNG_DATA_URI: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAIAAAACCAYAAABytg0kAAAAE0lEQVR4nGP4z8DwHwyBNAg0AABJSQl4KKDbdwAAAABJRU5ErkJggg==";
+
+    fn gltf_with_base_color_uri(uri: &str) -> Gltf {
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "images": [{{ "uri": "{}" }}],
+            "textures": [{{ "source": 0 }}],
+            "materials": [{{
+                "alphaMode": "BLEND",
+                "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }}
+            }}]
+        }}"#, uri);
+        Gltf::from_str(&json).unwrap().validate_minimally().unwrap()
+    }
+
+    #[test]
+    fn decodes_png_data_uri() {
+        let img = load_from_data_uri(PNG_DATA_URI).unwrap().to_rgba();
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(img.get_pixel(0, 0).data, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 1).data, [255, 255, 255, 128]);
+    }
+
+    #[test]
+    fn srgb_round_trips_through_linear() {
+        for value in 0..256 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value as u8)), value as u8);
+        }
+    }
+
+    #[test]
+    fn repeat_wrap_blends_resized_edges_with_opposite_side() {
+        let img = RgbImage::from_fn(2, 1, |x, _| if x == 0 { image::Rgb([0, 0, 0]) } else { image::Rgb([255, 255, 255]) });
+        let wrap = |mode| (mode, WrappingMode::ClampToEdge);
+        let clamped = resize_to(img.clone(), 4, 1, ResizeFilter::Triangle, wrap(WrappingMode::ClampToEdge));
+        let repeated = resize_to(img, 4, 1, ResizeFilter::Triangle, wrap(WrappingMode::Repeat));
+        assert_eq!(clamped.get_pixel(0, 0).data[0], 0);
+        assert!(repeated.get_pixel(0, 0).data[0] > 0);
+        assert_eq!(clamped.get_pixel(3, 0).data[0], 255);
+        assert!(repeated.get_pixel(3, 0).data[0] < 255);
+    }
+
+    #[test]
+    fn wraps_coordinates_by_mode() {
+        assert_eq!(wrap_coordinate(-1, 4, WrappingMode::ClampToEdge), 0);
+        assert_eq!(wrap_coordinate(-1, 4, WrappingMode::Repeat), 3);
+        assert_eq!(wrap_coordinate(-1, 4, WrappingMode::MirroredRepeat), 0);
+        assert_eq!(wrap_coordinate(5, 4, WrappingMode::MirroredRepeat), 2);
+    }
+
+    #[test]
+    fn rejects_mismatched_uv_layouts() {
+        let info: JsonValue = serde_json::from_str(
+            r#"{ "index": 0, "texCoord": 1, "extensions": { "KHR_texture_transform": { "scale": [2, 2] } } }"#).unwrap();
+        let scaled = TextureTransform::from_info_json(&info);
+        assert_eq!(scaled.scale, [2.0, 2.0]);
+        assert_eq!(scaled.offset, [0.0, 0.0]);
+        assert_eq!(tex_coord(&info), 1);
+
+        let identity = TextureTransform::from_info_json(&JsonValue::Null);
+        assert_eq!(tex_coord(&JsonValue::Null), 0);
+        assert!(validate_uv_layouts(vec![("base color", 1, scaled), ("occlusion", 1, scaled)].into_iter()).is_ok());
+        assert!(validate_uv_layouts(vec![("base color", 1, scaled), ("occlusion", 1, identity)].into_iter()).is_err());
+        match validate_uv_layouts(vec![("base color", 0, identity), ("occlusion", 1, identity)].into_iter()) {
+            Err(UnlitError::TexCoordMismatch { map: "occlusion", tex_coord: 1, .. }) => {},
+            result => panic!("unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn maps_uvs_between_texture_transforms() {
+        let from = TextureTransform { offset: [0.25, 0.5], rotation: 0.5, scale: [2.0, 4.0] };
+        let to = TextureTransform { offset: [-1.0, 0.0], rotation: -1.0, scale: [0.5, 0.5] };
+        let transform = |t: &TextureTransform, uv: [f32; 2]| UvTransform::between(&TextureTransform::default(), t).apply(uv);
+        let uv = [0.3, 0.7];
+        let mapped = UvTransform::between(&from, &to).apply(transform(&from, uv));
+        let expected = transform(&to, uv);
+        assert!((mapped[0] - expected[0]).abs() < 1e-5 && (mapped[1] - expected[1]).abs() < 1e-5);
+    }
+
+    #[test]
+    fn samples_occlusion_through_its_own_transform() {
+        let map = RgbImage::from_fn(2, 1, |x, _| image::Rgb([if x == 0 { 255 } else { 0 }, 0, 0]));
+        let identity = TextureTransform::default();
+        let offset = TextureTransform { offset: [0.5, 0.0], ..identity };
+        let uv = (UvTransform::between(&identity, &offset), (WrappingMode::Repeat, WrappingMode::Repeat));
+        let mut img = RgbaImage::from_pixel(2, 1, Rgba([255, 255, 255, 255]));
+        occlude_rows(&mut img, 0, 1, &map, Some(&uv), OcclusionParams { strength: 1.0, softened: false, gamma: 1.0, floor: 0.0 });
+        assert_eq!(img.into_raw(), vec![0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn reports_mismatched_dimensions() {
+        match validate_dimensions(vec![(2, 2), (4, 4)].into_iter()) {
+            Err(UnlitError::DimensionMismatch { expected: (2, 2), found: (4, 4) }) => {},
+            result => panic!("unexpected result: {:?}", result)
+        }
+        match validate_dimensions(vec![].into_iter()) {
+            Err(UnlitError::MissingTexture) => {},
+            result => panic!("unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn names_gpu_compressed_containers() {
+        let mut dds = b"DDS ".to_vec();
+        dds.extend_from_slice(&[0; 124]);
+        match decode_with_mime(&dds, "") {
+            Err(UnlitError::CompressedTexture("DDS")) => {},
+            result => panic!("unexpected result: {:?}", result.map(|img| img.dimensions()))
+        }
+    }
+
+    #[test]
+    fn rejects_non_base64_data_uri() {
+        assert!(load_from_data_uri("data:image/png,abc").is_err());
+    }
+
+    #[test]
+    fn generates_unlit_from_data_uri_base_color() {
+        let gltf = gltf_with_base_color_uri(PNG_DATA_URI);
+        let material = gltf.materials().next().unwrap();
+        let img = generate_unlit(&material, &JsonValue::Null, &TextureCache::new(&gltf, Path::new("."), vec![]), &UnlitOptions::default()).unwrap();
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(img.get_pixel(1, 0).data, [0, 255, 0, 255]);
+        assert_eq!(img.get_pixel(0, 1).data, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn decodes_buffer_views_of_data_uri_buffers() {
+        let png = base64::decode(&PNG_DATA_URI["data:image/png;base64,".len()..]).unwrap();
+        let json = format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ "uri": "data:application/octet-stream;base64,{}", "byteLength": {} }}],
+            "bufferViews": [{{ "buffer": 0, "byteLength": {} }}],
+            "images": [{{ "bufferView": 0, "mimeType": "image/png" }}],
+            "textures": [{{ "source": 0 }}],
+            "materials": [{{ "pbrMetallicRoughness": {{ "baseColorTexture": {{ "index": 0 }} }} }}]
+        }}"#, base64::encode(&png), png.len(), png.len());
+        let gltf = Gltf::from_str(&json).unwrap().validate_minimally().unwrap();
+        let textures = TextureCache::new(&gltf, Path::new("."), load_buffers(&gltf, Path::new("."), None));
+        let img = textures.load(&gltf.textures().next().unwrap()).unwrap();
+        assert_eq!(img.dimensions(), (2, 2));
+    }
+
+    #[test]
+    fn clamps_emissive_once_after_adding_it() {
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([200, 200, 0, 255]));
+        let emissive_map = RgbImage::from_pixel(1, 1, image::Rgb([100, 50, 100]));
+        apply_emissive(&mut img, &emissive_map, [1.0, 1.1, 3.0]);
+        assert_eq!(img.get_pixel(0, 0).data, [255, 255, 255, 255]);
+
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([100, 100, 100, 255]));
+        apply_emissive(&mut img, &emissive_map, [0.5, 0.5, 1.555]);
+        assert_eq!(img.get_pixel(0, 0).data, [150, 125, 255, 255]);
+    }
+
+    #[test]
+    fn generates_box_filtered_mips_down_to_1x1() {
+        let img = RgbaImage::from_fn(4, 2, |x, _| if x < 2 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 101, 3, 255]) });
+        let levels = generate_mips(img, MipFilter::Box);
+        let dimensions = levels.iter().map(|level| level.dimensions()).collect::<Vec<_>>();
+        assert_eq!(dimensions, vec![(4, 2), (2, 1), (1, 1)]);
+        assert_eq!(levels[1].get_pixel(1, 0).data, [255, 101, 3, 255]);
+        assert_eq!(levels[2].get_pixel(0, 0).data, [128, 51, 2, 255]);
+    }
+
+    #[test]
+    fn encodes_mips_as_ktx2() {
+        let levels = generate_mips(RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 40])), MipFilter::Box);
+        let mut data = Vec::new();
+        ktx::encode_ktx2(&mut data, &levels).unwrap();
+        assert!(ktx::is_ktx2(&data));
+        // The smallest level is stored first, right after the data format descriptor
+        assert_eq!(data.len(), 80 + 3 * 24 + 92 + 4 + 16 + 64);
+        assert_eq!(&data[80..88], &(80u64 + 3 * 24 + 92 + 4 + 16).to_le_bytes());
+        #[cfg(feature = "ktx2")]
+        assert_eq!(ktx::decode_ktx2(&data).unwrap().to_rgba().into_raw(), levels[0].clone().into_raw());
+    }
+
+    #[test]
+    fn encodes_layers_as_a_ktx2_array() {
+        let first = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 40]));
+        let second = RgbaImage::from_pixel(2, 2, Rgba([50, 60, 70, 80]));
+        let mut data = Vec::new();
+        ktx::encode_ktx2_array(&mut data, &[&first, &second]).unwrap();
+        // layerCount, then faceCount and levelCount
+        assert_eq!(&data[32..44], &[2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        let layers = data.len() - 32;
+        assert_eq!(&data[layers..layers + 16], &*first.into_raw());
+        assert_eq!(&data[layers + 16..], &*second.into_raw());
+    }
+
+    #[test]
+    fn dithers_between_8_bit_steps() {
+        let img = LinearImage::from_pixel(4, 4, Rgba([100.25 / 255.0, 0.0, 1.0, 0.5]));
+        let dithered = encode_linear_dithered(&img);
+        let red = dithered.pixels().map(|pixel| pixel.data[0] as u32).collect::<Vec<_>>();
+        // A quarter of the texels round up, keeping the mean of the gradient
+        assert_eq!(red.iter().sum::<u32>(), 100 * 16 + 4);
+        assert!(dithered.pixels().all(|pixel| pixel.data[1] == 0 && pixel.data[2] == 255 && pixel.data[3] == 128));
+        assert!(encode_linear(&img).pixels().all(|pixel| pixel.data[0] == 100));
+    }
+
+    #[test]
+    fn lists_the_files_of_images_and_image_buffers() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "uri": "images.bin", "byteLength": 4 }, { "uri": "geometry.bin", "byteLength": 4 }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 4 }],
+            "images": [{ "uri": "base.png" }, { "uri": "base.png" }, { "bufferView": 0, "mimeType": "image/png" }, { "uri": "data:image/png;base64," }]
+        }"#;
+        let gltf = Gltf::from_str(json).unwrap().validate_minimally().unwrap();
+        assert_eq!(referenced_files(&gltf, Path::new("dir")), vec![PathBuf::from("dir/base.png"), PathBuf::from("dir/images.bin")]);
+    }
+
+    #[test]
+    fn builds_options_from_the_command_line_defaults() {
+        let opts = UnlitOptions::builder().linear(true).scale(0.5).resize(Some(ResizeFilter::Nearest)).build();
+        assert_eq!(opts.default_size, Some(4));
+        assert!(opts.linear);
+        assert_eq!(opts.scale, 0.5);
+        assert_eq!(opts.resize, Some(ResizeFilter::Nearest));
+        assert_eq!(opts.gamma, UnlitOptions::default().gamma);
+    }
+
+    #[test]
+    fn decodes_shared_textures_once() {
+        let gltf = gltf_with_base_color_uri(PNG_DATA_URI);
+        let textures = TextureCache::new(&gltf, Path::new("."), vec![]);
+        let texture = gltf.textures().next().unwrap();
+        let first = textures.load(&texture).unwrap();
+        assert!(Arc::ptr_eq(&first, &textures.load(&texture).unwrap()));
+    }
+
+    #[test]
+    fn keeps_hdr_output_finite() {
+        let gltf = gltf_with_base_color_uri(PNG_DATA_URI);
+        let material = gltf.materials().next().unwrap();
+        let textures = TextureCache::new(&gltf, Path::new("."), vec![]);
+        for &exposure in &["NaN".parse().unwrap(), "1e9".parse().unwrap(), f32::INFINITY] {
+            let opts = UnlitOptions { exposure, ..UnlitOptions::default() };
+            let img = generate_unlit_hdr(&material, &JsonValue::Null, &textures, &opts).unwrap();
+            assert!(img.iter().all(|value| value.is_finite()), "exposure {}", exposure);
+        }
+
+        let mut img = LinearImage::from_pixel(1, 1, Rgba([f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 1e9]));
+        replace_non_finite(&mut img);
+        assert_eq!(img.get_pixel(0, 0).data, [0.0, HDR_MAX, 0.0, 1e9]);
+    }
+
+    #[test]
+    fn tags_pngs_as_srgb() {
+        let img = RgbaImage::from_pixel(2, 2, Rgba([255, 128, 0, 255]));
+        let mut tagged = vec![];
+        png16::encode_png(&mut tagged, &img, 2, 2, ColorType::RGBA(8), true).unwrap();
+        // The sRGB chunk directly follows the 13 byte IHDR chunk and its 12 bytes of framing
+        assert_eq!(&tagged[37..42], b"sRGB\0");
+        assert_eq!(decode_with_mime(&tagged, "image/png").unwrap().to_rgba().into_raw(), img.clone().into_raw());
+
+        let mut untagged = vec![];
+        png16::encode_png(&mut untagged, &img, 2, 2, ColorType::RGBA(8), false).unwrap();
+        assert!(!untagged.windows(4).any(|chunk| chunk == b"sRGB"));
+    }
+
+    #[test]
+    fn rounds_16_bit_pngs_to_8_bits() {
+        let img = Rgba16Image::from_fn(2, 1, |x, _| Rgba([0xFFFF, 0x00FF - x as u16 * 0x7F, 0x0080, 0x0000]));
+        let mut data = vec![];
+        png16::encode_png16(&mut data, &img, true).unwrap();
+        assert!(png16::is_png16(&data));
+        let decoded = decode_with_mime(&data, "image/png").unwrap().to_rgba();
+        // Truncating would drop both low bytes
+        assert_eq!(decoded.get_pixel(0, 0).data, [255, 1, 0, 0]);
+        assert_eq!(decoded.get_pixel(1, 0).data, [255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn applies_exposure_before_gamma() {
+        assert_eq!(tone(0.5, 1.0, 1.0), 0.5);
+        assert_eq!(tone(0.25, 1.0, 2.0), 0.5);
+        assert_eq!(tone(0.125, 2.0, 2.0), 0.5);
+    }
+
+    #[test]
+    fn white_occlusion_preserves_base_color() {
+        let base = RgbaImage::from_fn(16, 16, |x, y| Rgba([(y * 16 + x) as u8, 255 - (y * 16 + x) as u8, 7, 255]));
+        let white = RgbImage::from_pixel(16, 16, image::Rgb([255, 255, 255]));
+        for &strength in &[1.0, 2.0] {
+            let mut img = base.clone();
+            apply_occlusion(&mut img, &white, strength, 1.0, 0.0);
+            assert_eq!(img.into_raw(), base.clone().into_raw());
+        }
+    }
+
+    #[test]
+    fn samples_smaller_occlusion_bilinearly() {
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        let occlusion = RgbImage::from_fn(2, 2, |x, _| image::Rgb([if x == 0 { 255 } else { 0 }, 0, 0]));
+        apply_occlusion(&mut img, &occlusion, 1.0, 1.0, 0.0);
+        for y in 0..4 {
+            let row = (0..4).map(|x| img.get_pixel(x, y).data[0]).collect::<Vec<_>>();
+            assert_eq!(row, vec![255, 191, 63, 0]);
+        }
+    }
+
+    #[test]
+    fn rounds_scaled_dimensions_up() {
+        assert_eq!(scaled_dimensions(2048, 1024, 0.5), (1024, 512));
+        assert_eq!(scaled_dimensions(5, 3, 0.5), (3, 2));
+        assert_eq!(scaled_dimensions(4, 4, 0.01), (1, 1));
+        assert_eq!(scaled_dimensions(7, 9, 1.0), (7, 9));
+    }
+
+    #[test]
+    fn rounds_to_powers_of_two_after_scaling() {
+        let opts = UnlitOptions { scale: 0.5, power_of_two: Some(PowerOfTwo::Up), ..UnlitOptions::default() };
+        assert_eq!(final_dimensions(1000, 300, &opts), (512, 256));
+        let opts = UnlitOptions { power_of_two: Some(PowerOfTwo::Down), ..opts };
+        assert_eq!(final_dimensions(1000, 300, &opts), (256, 128));
+        assert_eq!(final_dimensions(1, 1, &opts), (1, 1));
+    }
+
+    #[test]
+    fn caps_dimensions_after_powers_of_two() {
+        assert_eq!(capped_dimensions(4096, 1024, 2048), (2048, 512));
+        assert_eq!(capped_dimensions(1000, 3, 100), (100, 1));
+        assert_eq!(capped_dimensions(300, 200, 2048), (300, 200));
+        let opts = UnlitOptions { power_of_two: Some(PowerOfTwo::Up), max_dimension: Some(1000), ..UnlitOptions::default() };
+        assert_eq!(final_dimensions(1000, 300, &opts), (1000, 500));
+    }
+
+    #[test]
+    fn computes_channel_stats() {
+        let mut img = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 10, 255]));
+        img.put_pixel(1, 1, Rgba([255, 100, 30, 0]));
+        let stats = texture_stats(&img);
+        assert_eq!(stats.min, [255, 0, 10, 0]);
+        assert_eq!(stats.max, [255, 100, 30, 255]);
+        assert_eq!(stats.mean, [255.0, 25.0, 15.0, 191.25]);
+        assert_eq!(stats.saturated, [1.0, 0.0, 0.0, 0.75]);
+    }
+
+    #[test]
+    fn reads_occlusion_from_the_selected_channel() {
+        let map = RgbImage::from_pixel(1, 1, image::Rgb([10, 20, 100]));
+        let selected = |channel| {
+            let mut map = map.clone();
+            select_occlusion_channel(&mut map, channel);
+            map.get_pixel(0, 0).data
+        };
+        assert_eq!(selected(OcclusionChannel::Red), [10, 20, 100]);
+        assert_eq!(selected(OcclusionChannel::Green), [20, 20, 100]);
+        assert_eq!(selected(OcclusionChannel::Blue), [100, 20, 100]);
+        assert_eq!(selected(OcclusionChannel::Luminance), [24, 20, 100]);
+    }
+
+    #[test]
+    fn rejects_output_of_the_wrong_size() {
+        let img = RgbaImage::new(4, 2);
+        assert!(validate_output(&img, (4, 2)).is_ok());
+        match validate_output(&img, (2, 4)) {
+            Err(UnlitError::InvalidOutput(_)) => {},
+            result => panic!("unexpected result: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn premultiplies_rgb_by_alpha() {
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([200, 255, 1, 128]));
+        premultiply_alpha(&mut img);
+        assert_eq!(img.get_pixel(0, 0).data, [100, 128, 1, 128]);
+    }
+
+    #[test]
+    fn flattens_onto_background() {
+        let mut img = RgbaImage::from_fn(3, 1, |x, _| Rgba([200, 100, 0, [255, 128, 0][x as usize]]));
+        flatten_onto(&mut img, [0, 0, 255], false);
+        assert_eq!(img.into_raw(), vec![200, 100, 0, 255, 100, 50, 127, 255, 0, 0, 255, 255]);
+
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([100, 50, 0, 128]));
+        flatten_onto(&mut img, [0, 0, 255], true);
+        assert_eq!(img.into_raw(), vec![100, 50, 127, 255]);
+    }
+
+    #[test]
+    fn darkens_smooth_metals_the_most() {
+        assert_eq!(metal_darkening(0.0, 0.0, 1.0), 1.0);
+        assert_eq!(metal_darkening(1.0, 0.0, 0.5), 0.5);
+        assert!(metal_darkening(1.0, 1.0, 0.5) > metal_darkening(1.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn warns_about_swapped_occlusion_and_emissive_maps() {
+        let gray = RgbImage::from_pixel(4, 4, image::Rgb([200, 200, 200]));
+        let colored = RgbImage::from_pixel(4, 4, image::Rgb([255, 40, 0]));
+        assert!(swapped_map_warnings(Some(&gray), Some(&colored), [1.0, 1.0, 1.0], false).is_empty());
+        assert_eq!(swapped_map_warnings(Some(&colored), Some(&gray), [1.0, 1.0, 1.0], false).len(), 2);
+        // A packed ORM texture and a tinted emissive are expected
+        assert!(swapped_map_warnings(Some(&colored), Some(&gray), [1.0, 0.5, 0.0], true).is_empty());
+    }
+
+    #[test]
+    fn thresholds_alpha_of_mask_materials() {
+        assert_eq!(scale_alpha(128, 1.0, None), 128);
+        assert_eq!(scale_alpha(128, 1.0, Some(0.5)), 255);
+        assert_eq!(scale_alpha(128, 0.5, Some(0.5)), 0);
+    }
+
+    #[test]
+    fn plans_material_without_baking() {
+        let gltf = gltf_with_base_color_uri(PNG_DATA_URI);
+        let material = gltf.materials().next().unwrap();
+        let textures = TextureCache::new(&gltf, Path::new("."), vec![]);
+        let plan = plan_material(&material, &JsonValue::Null, &textures, &UnlitOptions::default()).unwrap();
+        assert_eq!((plan.width, plan.height), (2, 2));
+        assert_eq!(plan.base_dimensions, Some((2, 2)));
+        assert_eq!(plan.occlusion_dimensions, None);
+        // Only the header of the PNG was read
+        assert!(textures.images[0].lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn generates_default_size_without_maps() {
+        let gltf = Gltf::from_str(r#"{ "asset": { "version": "2.0" }, "materials": [{}] }"#).unwrap().validate_minimally().unwrap();
+        let material = gltf.materials().next().unwrap();
+        let textures = TextureCache::new(&gltf, Path::new("."), vec![]);
+        assert!(generate_unlit(&material, &JsonValue::Null, &textures, &UnlitOptions::default()).is_err());
+        let opts = UnlitOptions { default_size: Some(4), ..UnlitOptions::default() };
+        let img = generate_unlit(&material, &JsonValue::Null, &textures, &opts).unwrap();
+        assert_eq!(img.dimensions(), (4, 4));
+        assert_eq!(img.get_pixel(0, 0).data, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn resolves_percent_encoded_and_absolute_uris() {
+        let dir = Path::new("/models");
+        assert_eq!(resolve_uri(dir, "base%20color.png"), Path::new("/models/base color.png"));
+        assert_eq!(resolve_uri(dir, "100%.png"), Path::new("/models/100%.png"));
+        assert_eq!(resolve_uri(dir, "/textures/base.png"), Path::new("/textures/base.png"));
+        assert_eq!(resolve_uri(dir, "file:///textures/base%20color.png"), Path::new("/textures/base color.png"));
+    }
+
+    #[test]
+    fn falls_back_to_relative_uri_on_disk() {
+        let gltf = gltf_with_base_color_uri("BotDefault_Avatar_img3.jpg");
+        let material = gltf.materials().next().unwrap();
+        let assets_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/assets");
+        assert!(generate_unlit(&material, &JsonValue::Null, &TextureCache::new(&gltf, &assets_dir, vec![]), &UnlitOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn finds_moved_textures_in_the_search_path() {
+        let gltf = gltf_with_base_color_uri("textures/BotDefault_Avatar_img3.jpg");
+        let texture = gltf.textures().next().unwrap();
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let search_path = [root.join("src"), root.join("test/assets")];
+        assert!(TextureCache::new(&gltf, root, vec![]).load(&texture).is_none());
+        let textures = TextureCache::new(&gltf, root, vec![]).with_search_path(&search_path);
+        assert_eq!(textures.load_dimensions(&texture), Some((2048, 2048)));
+        assert!(textures.load(&texture).is_some());
+    }
+}
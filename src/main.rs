@@ -1,54 +1,751 @@
+extern crate atty;
 extern crate image;
 extern crate clap;
+extern crate env_logger;
 extern crate gltf;
 extern crate gltf_json;
+extern crate gltf_unlit_generator;
+extern crate indicatif;
+#[macro_use]
+extern crate log;
+extern crate notify;
+extern crate rayon;
+extern crate regex;
+#[macro_use]
 extern crate serde_json;
+extern crate webp;
+extern crate zip;
 
+mod config;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
 use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
-use clap::{App, Arg, ArgMatches};
-use image::{DynamicImage, ImageError, Pixel, RgbImage, Rgba, RgbaImage};
-use gltf::{Gltf, Material, Texture};
-use gltf::image::Data;
+use clap::{App, Arg, ArgMatches, ErrorKind};
+use image::{ColorType, GenericImage, Rgb, RgbaImage};
+use image::hdr::HDREncoder;
+use image::jpeg::JPEGEncoder;
+use image::png::PNGEncoder;
+use gltf::{Glb, Gltf, Material};
 use gltf_json::material::AlphaMode;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::LevelFilter;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use gltf_unlit_generator::atlas::pack_atlas;
+use gltf_unlit_generator::contact_sheet::{contact_sheet, thumbnail};
+use gltf_unlit_generator::ktx::{encode_ktx2, encode_ktx2_array};
+use gltf_unlit_generator::png16::{encode_png, encode_png16};
+use gltf_unlit_generator::{base_color_factor, base_color_texture_json, encode_srgb16, flatten_onto, generate_component, generate_mips, generate_unlit, generate_unlit_hdr, encode_srgb, is_opaque, load_buffers, plan_material, referenced_files, select_occlusion_channel, texture_stats, validate_gltf, Component, LinearImage, MaterialPlan, MipFilter, OcclusionChannel, PowerOfTwo, Reconcile, ResizeFilter, Rgba16Image, TextureCache, TextureStats, UnlitError, UnlitFormat, UnlitOptions};
+use rayon::prelude::*;
+use regex::Regex;
 use serde_json::Value as JsonValue;
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+use config::{config_args, parse_config};
 
 #[derive(Debug)]
 struct Options<'a> {
     gltf: Gltf,
+    document: JsonValue,
+    /// Names the atlas and glTF written next to the textures.
+    stem: &'a str,
     gltf_dir: &'a Path,
-    out_dir: &'a Path,
-    lighten_factor: f32
+    textures: TextureCache,
+    /// Empty when writing into `zip`, so output paths are zip entry names.
+    out_dir: PathBuf,
+    zip: Option<&'a ZipOutput>,
+    format: OutputFormat,
+    /// Bits per channel of png output. 16-bit PNGs are written from the linear bake.
+    png_bit_depth: u8,
+    output_suffix: &'a str,
+    encoding: Encoding,
+    /// Color transparent texels are composited over for jpg output, or for every
+    /// format with `flatten`.
+    background: Option<[u8; 3]>,
+    flatten: bool,
+    write_gltf: bool,
+    dry_run: bool,
+    /// Print a description of every material instead of generating anything.
+    list: bool,
+    quiet: bool,
+    /// Overwrite existing output files instead of failing.
+    force: bool,
+    /// Print an object describing each material instead of only its output path.
+    json_verbose: bool,
+    /// Include the channel statistics of each texture in those objects.
+    stats: bool,
+    filter: MaterialFilter,
+    /// Output filenames and formats by material name or index, overriding the
+    /// default naming.
+    name_map: HashMap<String, NameMapEntry>,
+    /// The maximum atlas size when packing every texture into one atlas.
+    atlas: Option<u32>,
+    /// Where to write the mip chain of each texture, if anywhere.
+    mips: Option<Container>,
+    mip_filter: MipFilter,
+    /// Where to write same-sized textures as the layers of texture arrays instead
+    /// of separately.
+    array: Option<Container>,
+    /// Write only these contributions of each material, each as its own texture,
+    /// instead of its unlit texture.
+    only: Vec<Component>,
+    /// Also write the decoded source images of each material next to its texture.
+    extract_source: bool,
+    /// Keep a thumbnail of each texture for the --contact-sheet.
+    contact_sheet: bool,
+    unlit: UnlitOptions
+}
+
+impl<'a> Options<'a> {
+    /// The `--name-map` entry of a material, looked up by index before name.
+    fn name_map_entry(&self, mat: &Material) -> Option<&NameMapEntry> {
+        self.name_map.get(&mat.index().unwrap().to_string())
+            .or_else(|| mat.name().and_then(|name| self.name_map.get(name)))
+    }
+
+    /// The format a material is written in, from its `--name-map` entry or else
+    /// from `--format`, with png at `--bit-depth`.
+    fn material_format(&self, mat: &Material) -> OutputFormat {
+        match self.name_map_entry(mat).and_then(|entry| entry.format).unwrap_or(self.format).resolve(mat) {
+            OutputFormat::Png if self.png_bit_depth == 16 => OutputFormat::Png16,
+            format => format
+        }
+    }
+
+    /// The options a material is generated with, with the base color factor of
+    /// its `--name-map` entry if it has one.
+    fn material_unlit(&self, mat: &Material) -> Cow<'_, UnlitOptions> {
+        match self.name_map_entry(mat).and_then(|entry| entry.base_color_factor) {
+            Some(factor) => Cow::Owned(UnlitOptions { base_color_factor: Some(factor), ..self.unlit.clone() }),
+            None => Cow::Borrowed(&self.unlit)
+        }
+    }
+}
+
+/// A --zip archive every output is written into instead of the output directory.
+/// Materials finish in any order, so entries are held until [`ZipOutput::finish`]
+/// and written sorted by name to keep the archive reproducible.
+struct ZipOutput {
+    path: PathBuf,
+    file: File,
+    entries: Mutex<BTreeMap<String, Vec<u8>>>
+}
+
+impl fmt::Debug for ZipOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ZipOutput({})", self.path.display())
+    }
+}
+
+impl ZipOutput {
+    fn create(path: &Path, force: bool) -> Result<ZipOutput, Box<Error>> {
+        check_overwrite(path, force)?;
+        Ok(ZipOutput { path: path.to_path_buf(), file: File::create(path)?, entries: Mutex::new(BTreeMap::new()) })
+    }
+
+    /// Adds an entry named after a relative output path.
+    fn add(&self, name: &Path, data: &[u8]) -> Result<(), Box<Error>> {
+        let name = name.to_string_lossy().replace('\\', "/");
+        self.entries.lock().unwrap().insert(name, data.to_vec());
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Box<Error>> {
+        // Without the zip crate's time feature every entry is dated 1980-01-01
+        let mut writer = ZipWriter::new(self.file);
+        for (name, data) in self.entries.into_inner().unwrap() {
+            writer.start_file(name, FileOptions::default())?;
+            writer.write_all(&data)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// An explicit output for a material in a `--name-map` file.
+#[derive(Debug)]
+struct NameMapEntry {
+    filename: Option<String>,
+    format: Option<OutputFormat>,
+    /// Overrides `--base-color-factor` and the material's own factor.
+    base_color_factor: Option<[f32; 4]>
+}
+
+/// Selects which materials are generated by matching their names.
+#[derive(Debug)]
+struct MaterialFilter {
+    include: Option<Regex>,
+    exclude: Option<Regex>
 }
 
-fn process_args<'a>(matches: &'a ArgMatches<'a>) -> Result<Options<'a>, Box<Error>> {
-    let gltf_path = matches.value_of("input").ok_or("A GLTF file must be provided.")?;
-    let gltf_file = File::open(gltf_path)?;
-    let gltf_dir = Path::new(gltf_path).parent().ok_or("Invalid GLTF file path.")?;
-    let gltf = Gltf::from_reader(BufReader::new(gltf_file))?.validate_minimally()?;
-    let out_dir = matches.value_of("out").map(Path::new).unwrap_or(gltf_dir);
+impl MaterialFilter {
+    /// Unnamed materials are only skipped when an include pattern is given.
+    fn selects(&self, mat: &Material) -> bool {
+        match mat.name() {
+            Some(name) => self.include.as_ref().is_none_or(|include| include.is_match(name))
+                && !self.exclude.as_ref().is_some_and(|exclude| exclude.is_match(name)),
+            None => self.include.is_none()
+        }
+    }
+}
 
-    let lighten = matches.value_of("lighten").unwrap_or("0.0");
-    let lighten_factor = lighten.parse::<f32>()?;
+/// A texture written for a material.
+#[derive(Debug)]
+struct Generated {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    /// The channel statistics of 8-bit textures with --stats.
+    stats: Option<TextureStats>,
+    /// The texture shrunk to a cell of the --contact-sheet.
+    thumbnail: Option<RgbaImage>
+}
 
-    if lighten_factor < 0.0f32 || lighten_factor > 1.0f32 {
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Auto,
+    Png,
+    Jpeg,
+    Webp,
+    /// Radiance HDR, written from the unclamped linear bake.
+    Hdr,
+    /// PNG with 16 bits per channel, written from the linear bake.
+    Png16,
+    /// The raw RGBA8 bytes of the image with a JSON sidecar of its dimensions.
+    Raw
+}
+
+impl OutputFormat {
+    /// Resolves `Auto` to jpg for opaque materials and png for everything else.
+    fn resolve(self, mat: &Material) -> OutputFormat {
+        match (self, UnlitFormat::for_material(mat)) {
+            (OutputFormat::Auto, UnlitFormat::Jpeg) => OutputFormat::Jpeg,
+            (OutputFormat::Auto, UnlitFormat::Png) => OutputFormat::Png,
+            (format, _) => format
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Auto | OutputFormat::Png | OutputFormat::Png16 => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Raw => "rgba"
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "auto" => Ok(OutputFormat::Auto),
+            "png" => Ok(OutputFormat::Png),
+            "jpg" | "jpeg" => Ok(OutputFormat::Jpeg),
+            "webp" => Ok(OutputFormat::Webp),
+            "raw" => Ok(OutputFormat::Raw),
+            _ => Err(format!("Unknown output format: {}", s))
+        }
+    }
+}
+
+/// How the mip chain of a texture is written next to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Container {
+    /// One png per mip level or array layer, numbered from 0.
+    Png,
+    /// Every mip level or array layer in one uncompressed KTX2 image.
+    Ktx2
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Container, String> {
+        match s {
+            "png" => Ok(Container::Png),
+            "ktx2" => Ok(Container::Ktx2),
+            _ => Err(format!("Unknown container: {}", s))
+        }
+    }
+}
+
+/// Settings for the lossy output encoders.
+#[derive(Clone, Copy, Debug)]
+struct Encoding {
+    jpeg_quality: u8,
+    webp_quality: f32,
+    webp_lossless: bool,
+    /// Tag PNGs with an sRGB chunk. Off for --no-color-profile and linear output.
+    srgb_chunk: bool
+}
+
+fn process_args<'a>(matches: &'a ArgMatches<'a>, input: &'a str, zip: Option<&'a ZipOutput>) -> Result<Options<'a>, Box<Error>> {
+    let gltf_path = Path::new(input);
+    let mut gltf_data = Vec::new();
+
+    // Relative uris resolve from the input's directory unless --base-dir overrides it.
+    // A document read from stdin has no directory, so it must be given one.
+    let base_dir = matches.value_of("base_dir").map(Path::new);
+    let (input_dir, stem) = if gltf_path == Path::new("-") {
+        let base_dir = base_dir.ok_or("--base-dir must be provided when reading a GLTF file from stdin.")?;
+        io::stdin().read_to_end(&mut gltf_data)?;
+        (base_dir, "stdin")
+    } else {
+        File::open(gltf_path)?.read_to_end(&mut gltf_data)?;
+        let input_dir = gltf_path.parent().ok_or("Invalid GLTF file path.")?;
+        let stem = gltf_path.file_stem().and_then(|s| s.to_str()).ok_or("Invalid GLTF file path.")?;
+        (input_dir, stem)
+    };
+    let gltf_dir = base_dir.unwrap_or(input_dir);
+
+    // Binary glTF keeps its JSON and buffer data in separate chunks.
+    // The raw JSON is kept around for extensions the gltf crate does not deserialize.
+    let (gltf, document, blob) = if is_glb(gltf_path, &gltf_data) {
+        let glb = Glb::from_slice(&gltf_data)?;
+        let document = serde_json::from_slice(&glb.json)?;
+        (validate_gltf(Gltf::from_glb(&glb)?)?, document, glb.bin.map(|bin| bin.into_owned()))
+    } else {
+        let document = serde_json::from_slice(&gltf_data)?;
+        (validate_gltf(Gltf::from_slice(&gltf_data)?)?, document, None)
+    };
+    let out_dir = match matches.value_of("out") {
+        Some(_) if zip.is_some() => PathBuf::new(),
+        Some(out) => PathBuf::from(expand_path(out)?),
+        None if zip.is_some() => PathBuf::new(),
+        None => input_dir.to_path_buf()
+    };
+
+    let lighten_factor = parse_lighten(matches.value_of("lighten").unwrap_or("0.0"))?;
+    let base_color_factor = match matches.value_of("base_color_factor") {
+        Some(factor) => Some(parse_base_color_factor(factor.split(',').map(|c| parse_finite(c.trim(), "Base color factor")).collect::<Result<Vec<_>, _>>()?)?),
+        None => None
+    };
+
+    let exposure = parse_finite(matches.value_of("exposure").unwrap_or("1.0"), "Exposure")?;
+    // parse_finite already rejects NaN
+    if exposure < 0.0 {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Exposure value must not be negative."))));
+    }
+
+    let gamma = parse_finite(matches.value_of("gamma").unwrap_or("1.0"), "Gamma")?;
+    if gamma <= 0.0 {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Gamma value must be greater than 0.0."))));
+    }
+
+    let metal_darken = parse_finite(matches.value_of("metal_darken").unwrap_or("0.0"), "Metal darken")?;
+    if !(0.0..=1.0).contains(&metal_darken) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Metal darken value must be between 0.0 and 1.0."))));
+    }
+
+    let format = if matches.is_present("hdr") {
+        OutputFormat::Hdr
+    } else if matches.is_present("png16") {
+        OutputFormat::Png16
+    } else {
+        matches.value_of("format").unwrap_or("auto").parse::<OutputFormat>()?
+    };
+
+    let png_bit_depth = matches.value_of("bit_depth").unwrap_or("8").parse::<u8>()?;
+    if png_bit_depth == 16 {
+        // Like --png16, these need the 8-bit bake
+        let eight_bit_only = ["mips", "output_linear", "dither", "premultiply", "background", "atlas", "array", "only"];
+        if let Some(arg) = eight_bit_only.iter().find(|arg| matches.is_present(arg)) {
+            return Err(Box::new(clap::Error::value_validation_auto(format!("--bit-depth 16 cannot be combined with --{}.", arg.replace('_', "-")))));
+        }
+    }
+
+    let write_gltf = matches.is_present("write_gltf");
+
+    let output_suffix = matches.value_of("output_suffix").unwrap_or("_unlit");
+    let jpeg_quality = matches.value_of("quality").unwrap_or("90").parse::<u8>()?;
+
+    if !(1..=100).contains(&jpeg_quality) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Quality value must be between 1 and 100."))));
+    }
+
+    let webp_quality = parse_finite(matches.value_of("webp_quality").unwrap_or("90"), "WebP quality")?;
+    if !(0.0..=100.0).contains(&webp_quality) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("WebP quality value must be between 0 and 100."))));
+    }
+    let srgb_chunk = !matches.is_present("no_color_profile") && !matches.is_present("output_linear");
+    let encoding = Encoding { jpeg_quality, webp_quality, webp_lossless: matches.is_present("webp_lossless"), srgb_chunk };
+
+    let atlas = if matches.is_present("atlas") {
+        Some(matches.value_of("atlas_max_size").unwrap_or("4096").parse::<u32>()?)
+    } else {
+        None
+    };
+
+    // A dry run or listing writes nothing, not even the output directory
+    let dry_run = matches.is_present("dry_run");
+    let list = matches.is_present("list");
+    if !dry_run && !list && zip.is_none() {
+        fs::create_dir_all(&out_dir)?;
+    }
+    let search_path = match matches.values_of("search_path") {
+        Some(dirs) => dirs.map(|dir| expand_path(dir).map(PathBuf::from)).collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new()
+    };
+    let textures = TextureCache::new(&gltf, gltf_dir, load_buffers(&gltf, gltf_dir, blob)).with_search_path(&search_path);
+    let linear = matches.is_present("linear");
+    // --reconcile resize is another way of writing --resize
+    let reconcile = matches.value_of("reconcile");
+    let resize = if matches.is_present("resize") || reconcile == Some("resize") {
+        Some(matches.value_of("resize_filter").unwrap_or("triangle").parse::<ResizeFilter>()?)
+    } else {
+        None
+    };
+    let reconcile = match reconcile {
+        Some("resize") | None => None,
+        Some(reconcile) => Some(reconcile.parse::<Reconcile>()?)
+    };
+    let default_size = matches.value_of("default_size").unwrap_or("4").parse::<u32>()?;
+    if default_size == 0 {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Default size must be at least 1."))));
+    }
+    let premultiply = matches.is_present("premultiply");
+    let scale = parse_finite(matches.value_of("scale").unwrap_or("1.0"), "Scale")?;
+    if !(scale > 0.0 && scale <= 1.0) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Scale value must be greater than 0.0 and at most 1.0."))));
+    }
+    let ao_floor = parse_finite(matches.value_of("ao_floor").unwrap_or("0.0"), "AO floor")?;
+    if !(0.0..=1.0).contains(&ao_floor) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("AO floor value must be between 0.0 and 1.0."))));
+    }
+    let ao_gamma = parse_finite(matches.value_of("ao_gamma").unwrap_or("1.0"), "AO gamma")?;
+    if ao_gamma <= 0.0 {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("AO gamma value must be greater than 0.0."))));
+    }
+    let occlusion_channel = matches.value_of("occlusion_channel").unwrap_or("r").parse::<OcclusionChannel>()?;
+    let occlusion_strength = match matches.value_of("occlusion_strength") {
+        Some(strength) => Some(parse_finite(strength, "Occlusion strength")?),
+        None => None
+    };
+    // Read like the materials' own occlusion maps, from the chosen channel
+    let extra_occlusion = match matches.value_of("extra_occlusion") {
+        Some(path) => {
+            let mut map = image::open(expand_path(path)?)?.to_rgb();
+            select_occlusion_channel(&mut map, occlusion_channel);
+            Some(Arc::new(map))
+        },
+        None => None
+    };
+    let power_of_two = if matches.is_present("pot") {
+        Some(matches.value_of("pot_mode").unwrap_or("up").parse::<PowerOfTwo>()?)
+    } else {
+        None
+    };
+    let max_dimension = match matches.value_of("max_dimension") {
+        Some(max_dimension) => Some(max_dimension.parse::<u32>()?),
+        None => None
+    };
+    if max_dimension == Some(0) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Max dimension must be at least 1."))));
+    }
+    let tile_height = match matches.value_of("tile_height") {
+        Some(tile_height) => Some(tile_height.parse::<u32>()?),
+        None => None
+    };
+    if tile_height == Some(0) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Tile height must be at least 1."))));
+    }
+    let unlit = UnlitOptions::builder()
+        .lighten_factor(lighten_factor)
+        .base_color_factor(base_color_factor)
+        .exposure(exposure)
+        .gamma(gamma)
+        .metal_darken(metal_darken)
+        .linear(linear)
+        .output_linear(matches.is_present("output_linear"))
+        .resize(resize)
+        .reconcile(reconcile)
+        .default_size(Some(default_size))
+        .premultiply(premultiply)
+        .scale(scale)
+        .ao_floor(ao_floor)
+        .ao_gamma(ao_gamma)
+        .occlusion_channel(occlusion_channel)
+        .occlusion_strength(occlusion_strength)
+        .extra_occlusion(extra_occlusion)
+        .dither(matches.is_present("dither"))
+        .power_of_two(power_of_two)
+        .max_dimension(max_dimension)
+        .sanity_check(matches.is_present("sanity_check"))
+        .solid(matches.is_present("solid"))
+        .flip_v(matches.is_present("flip_v"))
+        .tile_height(tile_height)
+        .build();
+    let quiet = matches.is_present("quiet");
+    let background = match matches.value_of("background") {
+        Some(background) => Some(parse_background(background)?),
+        None => None
+    };
+    let flatten = matches.is_present("flatten");
+    let mips = match matches.value_of("mips") {
+        Some(mips) => Some(mips.parse::<Container>()?),
+        None => None
+    };
+    let array = match matches.value_of("array") {
+        Some(array) => Some(array.parse::<Container>()?),
+        None => None
+    };
+    let mip_filter = matches.value_of("mip_filter").unwrap_or("box").parse::<MipFilter>()?;
+    let name_map = match matches.value_of("name_map") {
+        Some(path) => parse_name_map(Path::new(path))?,
+        None => HashMap::new()
+    };
+    if write_gltf && name_map.values().any(|entry| entry.format == Some(OutputFormat::Raw)) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Raw output in the name map cannot be referenced by a glTF."))));
+    }
+    let stats = matches.is_present("stats");
+    let extract_source = matches.is_present("extract_source");
+    let contact_sheet = matches.is_present("contact_sheet");
+    let only = match matches.values_of("only") {
+        Some(components) => components.map(|component| component.parse::<Component>()).collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new()
+    };
+    let json_verbose = matches.is_present("json_verbose") || stats;
+    let force = matches.is_present("force");
+    let filter = MaterialFilter {
+        include: matches.value_of("include").map(Regex::new).map_or(Ok(None), |r| r.map(Some))?,
+        exclude: matches.value_of("exclude").map(Regex::new).map_or(Ok(None), |r| r.map(Some))?
+    };
+    Ok(Options { gltf, document, stem, gltf_dir, textures, out_dir, zip, format, png_bit_depth, output_suffix, encoding, background, flatten, write_gltf, dry_run, list, quiet, force, json_verbose, stats, filter, name_map, atlas, mips, mip_filter, array, only, extract_source, contact_sheet, unlit })
+}
+
+/// Reads a `--name-map` file, an object keyed by material name or index whose
+/// values are either an output filename or an object with an optional
+/// `filename` and `format`. A filename alone picks the format from its extension.
+fn parse_name_map(path: &Path) -> Result<HashMap<String, NameMapEntry>, Box<Error>> {
+    let document: JsonValue = serde_json::from_reader(io::BufReader::new(File::open(path)?))?;
+    let entries = document.as_object().ok_or("The name map must be a JSON object.")?;
+    let mut name_map = HashMap::new();
+    for (key, value) in entries {
+        let (filename, format, factor) = match *value {
+            JsonValue::String(ref filename) => (Some(filename.as_str()), None, None),
+            JsonValue::Object(ref entry) => (
+                entry.get("filename").and_then(|v| v.as_str()),
+                entry.get("format").and_then(|v| v.as_str()),
+                entry.get("baseColorFactor")
+            ),
+            _ => return Err(From::from(format!("The name map entry of {} must be a filename or an object.", key)))
+        };
+        let format = match format {
+            Some(format) => Some(format.parse::<OutputFormat>()?),
+            None => filename.and_then(|filename| Path::new(filename).extension())
+                .and_then(|ext| ext.to_str())
+                .and_then(|ext| ext.to_lowercase().parse::<OutputFormat>().ok())
+        };
+        let base_color_factor = match factor {
+            Some(factor) => {
+                let components = factor.as_array().map_or(Vec::new(), |values| values.iter().map(|v| v.as_f64().map_or(-1.0, |v| v as f32)).collect());
+                Some(parse_base_color_factor(components)?)
+            },
+            None => None
+        };
+        name_map.insert(key.clone(), NameMapEntry { filename: filename.map(String::from), format, base_color_factor });
+    }
+    Ok(name_map)
+}
+
+/// Reads a `--config` file into the arguments it stands for, leaving out options
+/// already given in `cli_args` or conflicting with them. Unknown keys are warned about, since the
+/// logger is not set up yet, rather than failing.
+fn read_config(app: &App, matches: &ArgMatches, cli_args: &[OsString], path: &Path) -> Result<(Vec<String>, Vec<String>), Box<Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let mut args = Vec::new();
+    let mut warnings = Vec::new();
+    for (key, value) in parse_config(&text)? {
+        let name = key.replace('-', "_");
+        let flag = format!("--{}", key.replace('_', "-"));
+        // Unknown flags are the only parse error a lone flag can cause
+        let probe = app.clone().get_matches_from_safe(vec![String::from("gltf_unlit_generator"), flag]);
+        if name == "config" || probe.map_err(|e| e.kind).err() == Some(ErrorKind::UnknownArgument) {
+            warnings.push(format!("Ignoring unknown option {} in {}.", key, path.display()));
+        } else if !matches.is_present(&name) {
+            let config_args = config_args(&key, &value)?;
+            // An option conflicting with one given on the command line is overridden by it too
+            let mut argv = vec![OsString::from("gltf_unlit_generator")];
+            argv.extend(config_args.iter().map(OsString::from));
+            argv.extend(cli_args.iter().cloned());
+            let probe = app.clone().get_matches_from_safe(argv);
+            if probe.map_err(|e| e.kind).err() != Some(ErrorKind::ArgumentConflict) {
+                args.extend(config_args);
+            }
+        }
+    }
+    Ok((args, warnings))
+}
+
+/// Expands an input path like [`expand_path`], leaving `-` for stdin as it is.
+fn expand_input(input: &str) -> Result<String, Box<Error>> {
+    if input == "-" {
+        Ok(String::from(input))
+    } else {
+        expand_path(input)
+    }
+}
+
+/// Expands a leading `~` to the home directory and `$VAR` or `${VAR}` to the
+/// value of the environment variable, for paths a shell did not expand.
+fn expand_path(path: &str) -> Result<String, Box<Error>> {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        let home = env::var("HOME").or_else(|_| env::var("USERPROFILE"))
+            .map_err(|_| "Unable to expand ~, the home directory is not set.")?;
+        expanded.push_str(&home);
+        rest = &rest[1..];
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = if after.starts_with('{') {
+            let end = after.find('}').filter(|&end| end > 1).ok_or_else(|| format!("Invalid ${{}} in path {}.", path))?;
+            (&after[1..end], end + 1)
+        } else {
+            let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+        if name.is_empty() {
+            // A lone $ is part of the path
+            expanded.push('$');
+        } else {
+            let value = env::var(name).map_err(|_| format!("Environment variable {} in path {} is not set.", name, path))?;
+            expanded.push_str(&value);
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Parses a lighten value of either one scalar for every channel or `R,G,B`
+/// components, each 0.0 - 1.0.
+fn parse_lighten(value: &str) -> Result<[f32; 3], Box<Error>> {
+    let components = value.split(',').map(|c| parse_finite(c.trim(), "Lighten")).collect::<Result<Vec<_>, _>>()?;
+    let lighten = match components.len() {
+        1 => [components[0]; 3],
+        3 => [components[0], components[1], components[2]],
+        _ => return Err(Box::new(clap::Error::value_validation_auto(String::from("Lighten must be one value or three comma separated values."))))
+    };
+    if lighten.iter().any(|c| !(0.0..=1.0).contains(c)) {
         return Err(Box::new(clap::Error::value_validation_auto(String::from("Lighten value must be between 0.0 and 1.0."))));
     }
+    Ok(lighten)
+}
+
+/// Parses a number, rejecting the "NaN" and "inf" that `f32::from_str` accepts,
+/// since NaN passes every range check and either would spread through the bake.
+fn parse_finite(value: &str, name: &str) -> Result<f32, Box<Error>> {
+    let number = value.parse::<f32>()?;
+    if !number.is_finite() {
+        return Err(Box::new(clap::Error::value_validation_auto(format!("{} value must be a finite number, not {}.", name, value))));
+    }
+    Ok(number)
+}
+
+/// Checks that a base color factor is four values between 0.0 and 1.0.
+fn parse_base_color_factor(components: Vec<f32>) -> Result<[f32; 4], Box<Error>> {
+    if components.len() != 4 || components.iter().any(|c| !(0.0..=1.0).contains(c)) {
+        return Err(Box::new(clap::Error::value_validation_auto(String::from("Base color factor must be four comma separated values between 0.0 and 1.0."))));
+    }
+    Ok([components[0], components[1], components[2], components[3]])
+}
+
+/// Parses an `R,G,B` color of 0 - 255 components.
+fn parse_background(value: &str) -> Result<[u8; 3], Box<Error>> {
+    let components = value.split(',').map(|c| c.trim().parse::<u8>()).collect::<Result<Vec<_>, _>>();
+    match components {
+        Ok(ref c) if c.len() == 3 => Ok([c[0], c[1], c[2]]),
+        _ => Err(Box::new(clap::Error::value_validation_auto(String::from("Background must be three comma separated values between 0 and 255."))))
+    }
+}
+
+/// Composites `img` over the background color if `format` should be flattened,
+/// returning whether it was. `premultiplied` is whether its RGB channels are
+/// already multiplied by alpha, see [`is_premultiplied`].
+fn apply_background(opts: &Options, img: &mut RgbaImage, format: OutputFormat, premultiplied: bool) -> bool {
+    match opts.background {
+        Some(background) if format == OutputFormat::Jpeg || opts.flatten => {
+            flatten_onto(img, background, premultiplied);
+            true
+        },
+        _ => false
+    }
+}
+
+/// Returns true if the unlit texture of `material` is premultiplied. Opaque
+/// materials are never premultiplied, even with --premultiply.
+fn is_premultiplied(opts: &Options, material: &Material) -> bool {
+    opts.unlit.premultiply && !is_opaque(material)
+}
+
+/// Sizes the global thread pool shared by every input.
+fn configure_jobs(matches: &ArgMatches) -> Result<(), Box<Error>> {
+    if let Some(jobs) = matches.value_of("jobs") {
+        let jobs = jobs.parse::<usize>()?;
+        if jobs == 0 {
+            return Err(Box::new(clap::Error::value_validation_auto(String::from("Jobs value must be at least 1."))));
+        }
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global()?;
+    }
+    Ok(())
+}
 
-    fs::create_dir_all(out_dir)?;
-    Ok(Options { gltf, gltf_dir, out_dir, lighten_factor })
+fn is_glb(path: &Path, data: &[u8]) -> bool {
+    let has_glb_extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("glb"));
+    has_glb_extension || gltf::is_binary(data)
 }
 
+/// Exit status when every input and material succeeded.
+const EXIT_OK: i32 = 0;
+/// Exit status of a fatal error, such as invalid arguments, an input that could
+/// not be processed, or a glTF, archive, manifest, or contact sheet that could
+/// not be written. With --strict, failed materials are fatal too. clap also
+/// exits with it on invalid arguments.
+const EXIT_FATAL: i32 = 1;
+/// Exit status when the run otherwise succeeded but some materials failed and
+/// were printed as null.
+const EXIT_MATERIALS_FAILED: i32 = 2;
+
 fn main() {
-    let matches = App::new("gltf_unlit_generator")
+    let app = App::new("gltf_unlit_generator")
         .version("0.1")
-        .about("Generates an unlit texture for a .gltf file.")
-        .args_from_usage("[input] 'input .gltf file'")
+        .about("Generates an unlit texture for a .gltf or .glb file.")
+        .args_from_usage("[input]... 'input .gltf or .glb files, or - to read from stdin'")
+        .arg(Arg::with_name("base_dir")
+            .long("base-dir")
+            .value_name("dir")
+            .help("Directory relative buffer and image uris are resolved from. Defaults to the directory of the input file and is required when reading from stdin.")
+            .takes_value(true))
+        .arg(Arg::with_name("search_path")
+            .long("search-path")
+            .value_name("dir")
+            .help("Directory to look in, by file name, for images missing from their uris. Repeat to search several in order.")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true))
         .arg(Arg::with_name("out")
             .short("o")
             .long("out")
@@ -59,150 +756,1373 @@ fn main() {
             .short("l")
             .long("lighten")
             .value_name("lighten")
-            .help("Scalar value 0.0 - 1.0 to be added to the RGB channels of the base color map.")
+            .help("Value 0.0 - 1.0 to be added to the RGB channels of the base color map, or R,G,B values to add to each channel.")
+            .takes_value(true))
+        .arg(Arg::with_name("base_color_factor")
+            .long("base-color-factor")
+            .value_name("factor")
+            .help("R,G,B,A values 0.0 - 1.0 used as the base color factor of every material instead of its own. A baseColorFactor in the --name-map takes precedence.")
+            .takes_value(true))
+        .arg(Arg::with_name("exposure")
+            .long("exposure")
+            .value_name("exposure")
+            .help("Scale applied to the base color in linear light before gamma and lighten. Defaults to 1.0.")
+            .takes_value(true))
+        .arg(Arg::with_name("gamma")
+            .long("gamma")
+            .value_name("gamma")
+            .help("Raises the exposed base color to 1 / gamma. Values above 1.0 brighten midtones. Defaults to 1.0.")
+            .takes_value(true))
+        .arg(Arg::with_name("metal_darken")
+            .long("metal-darken")
+            .value_name("metal_darken")
+            .help("Scalar value 0.0 - 1.0 darkening smooth metallic areas to approximate their lost reflections.")
+            .takes_value(true))
+        .arg(Arg::with_name("ao_floor")
+            .long("ao-floor")
+            .value_name("ao_floor")
+            .help("Scalar value 0.0 - 1.0 below which occlusion never darkens the base color, whatever the occlusion strength.")
+            .takes_value(true))
+        .arg(Arg::with_name("ao_gamma")
+            .long("ao-gamma")
+            .value_name("ao_gamma")
+            .help("Gamma curve applied to occlusion before it darkens the base color. Values above 1.0 soften shadows and values below deepen them. Defaults to 1.0.")
+            .takes_value(true))
+        .arg(Arg::with_name("occlusion_channel")
+            .long("occlusion-channel")
+            .value_name("channel")
+            .help("Channel of the occlusion texture to read occlusion from. Defaults to r, as glTF specifies.")
+            .possible_values(&["r", "g", "b", "luminance"])
+            .takes_value(true))
+        .arg(Arg::with_name("occlusion_strength")
+            .long("occlusion-strength")
+            .value_name("strength")
+            .help("Scalar value 0.0 - 1.0 used as the occlusion strength of every material with an occlusion texture, instead of its own. Lower values soften occlusion, from none at 0.0 to the full occlusion map at 1.0. Values outside that range are clamped.")
+            .takes_value(true))
+        .arg(Arg::with_name("extra_occlusion")
+            .long("extra-occlusion")
+            .value_name("path")
+            .help("Additional occlusion map multiplied on top of each material's, such as macro occlusion baked separately. It must be the size of the unlit textures.")
+            .takes_value(true))
+        .arg(Arg::with_name("format")
+            .short("f")
+            .long("format")
+            .value_name("format")
+            .help("Output image format. auto uses jpg for opaque materials and png otherwise. raw writes the RGBA8 bytes of each texture and a .json file of its dimensions.")
+            .possible_values(&["auto", "png", "jpg", "webp", "raw"])
+            .takes_value(true))
+        .arg(Arg::with_name("mips")
+            .long("mips")
+            .value_name("container")
+            .help("Also write a mip chain of each texture down to 1x1, as numbered png levels or one ktx2 image.")
+            .possible_values(&["png", "ktx2"])
+            .conflicts_with_all(&["hdr", "png16", "atlas"])
+            .takes_value(true))
+        .arg(Arg::with_name("mip_filter")
+            .long("mip-filter")
+            .value_name("filter")
+            .help("Filter used to downsample each mip level from the one above it. Defaults to box.")
+            .possible_values(&["box", "nearest", "triangle", "catmullrom", "gaussian", "lanczos3"])
+            .requires("mips")
+            .takes_value(true))
+        .arg(Arg::with_name("output_suffix")
+            .long("output-suffix")
+            .value_name("suffix")
+            .help("Appended to the material name of each output file. Defaults to _unlit.")
+            .takes_value(true))
+        .arg(Arg::with_name("quality")
+            .short("q")
+            .long("quality")
+            .value_name("quality")
+            .help("JPEG quality 1 - 100. Defaults to 90.")
+            .takes_value(true))
+        .arg(Arg::with_name("webp_quality")
+            .long("webp-quality")
+            .value_name("quality")
+            .help("WebP quality 0 - 100. Defaults to 90.")
+            .conflicts_with("webp_lossless")
+            .takes_value(true))
+        .arg(Arg::with_name("webp_lossless")
+            .long("webp-lossless")
+            .help("Encode WebP output losslessly."))
+        .arg(Arg::with_name("no_color_profile")
+            .long("no-color-profile")
+            .help("Do not tag png output with an sRGB chunk. Linear output is never tagged."))
+        .arg(Arg::with_name("hdr")
+            .long("hdr")
+            .help("Composite in linear light without clamping and write Radiance .hdr files.")
+            .conflicts_with("format"))
+        .arg(Arg::with_name("png16")
+            .long("png16")
+            .help("Composite in linear light and write 16-bit PNG files. Input maps are still read at 8 bits per channel.")
+            .conflicts_with_all(&["format", "hdr"]))
+        .arg(Arg::with_name("bit_depth")
+            .long("bit-depth")
+            .value_name("bits")
+            .help("Bits per channel of png output. 16-bit PNGs are composited in linear light like --png16, while jpg and other formats are unaffected. Defaults to 8.")
+            .possible_values(&["8", "16"])
+            .takes_value(true)
+            .conflicts_with_all(&["hdr", "png16"]))
+        .arg(Arg::with_name("linear")
+            .long("linear")
+            .help("Composite the base color, occlusion, and emissive maps in linear light instead of sRGB."))
+        .arg(Arg::with_name("output_linear")
+            .long("output-linear")
+            .help("Write linear instead of sRGB colors, for engines that import unlit textures as linear data. Independent of --linear, which only changes how maps are composited, so combining them does not convert twice.")
+            .conflicts_with_all(&["hdr", "png16"]))
+        .arg(Arg::with_name("dither")
+            .long("dither")
+            .help("Dither the --linear bake when quantizing it to 8 bits, to hide banding in smooth gradients. Has no effect without --linear.")
+            .conflicts_with_all(&["hdr", "png16"]))
+        .arg(Arg::with_name("resize")
+            .long("resize")
+            .help("Resample maps with differing dimensions up to the largest map instead of failing."))
+        .arg(Arg::with_name("resize_filter")
+            .long("resize-filter")
+            .value_name("filter")
+            .help("Filter used by --resize and --reconcile resize. Defaults to triangle.")
+            .possible_values(&["nearest", "triangle", "catmullrom", "gaussian", "lanczos3"])
+            .takes_value(true))
+        .arg(Arg::with_name("reconcile")
+            .long("reconcile")
+            .value_name("mode")
+            .help("How maps with differing dimensions are reconciled instead of failing. crop centers every map on the base color map's size, pad centers them on the largest size, and resize is --resize. Crop and pad keep texels 1:1.")
+            .possible_values(&["crop", "pad", "resize"])
+            .conflicts_with("resize")
+            .takes_value(true))
+        .arg(Arg::with_name("default_size")
+            .long("default-size")
+            .value_name("size")
+            .help("Size of the texture generated for materials without any maps. Defaults to 4.")
+            .takes_value(true))
+        .arg(Arg::with_name("solid")
+            .long("solid")
+            .help("Ignore every map and generate a solid texture of each material's base color factor at --default-size."))
+        .arg(Arg::with_name("scale")
+            .long("scale")
+            .value_name("scale")
+            .help("Factor greater than 0.0 and at most 1.0 the finished texture is downscaled by. Defaults to 1.0.")
+            .takes_value(true))
+        .arg(Arg::with_name("pot")
+            .long("pot")
+            .help("Resize each finished texture to power of two dimensions, after --scale."))
+        .arg(Arg::with_name("pot_mode")
+            .long("pot-mode")
+            .value_name("mode")
+            .help("Whether --pot rounds each side up or down to a power of two. Defaults to up.")
+            .possible_values(&["up", "down"])
+            .requires("pot")
+            .takes_value(true))
+        .arg(Arg::with_name("max_dimension")
+            .long("max-dimension")
+            .value_name("pixels")
+            .help("Proportionally downscale textures larger than this on either side, after --scale and --pot.")
+            .takes_value(true))
+        .arg(Arg::with_name("flip_v")
+            .long("flip-v")
+            .help("Flip each finished texture vertically, for engines with a bottom left UV origin.")
+            .conflicts_with("write_gltf"))
+        .arg(Arg::with_name("tile_height")
+            .long("tile-height")
+            .value_name("rows")
+            .help("Composite each texture in bands of this many rows in parallel. Speeds up very large textures on models with few materials.")
+            .takes_value(true))
+        .arg(Arg::with_name("premultiply")
+            .long("premultiply")
+            .help("Multiply the RGB channels of transparent materials by their alpha. jpg output has no alpha channel, so it is incompatible with premultiplied alpha.")
+            .conflicts_with_all(&["hdr", "png16"]))
+        .arg(Arg::with_name("background")
+            .long("background")
+            .value_name("R,G,B")
+            .help("Composite transparent materials over this 0 - 255 color before jpg output drops their alpha channel.")
+            .conflicts_with_all(&["hdr", "png16"])
+            .takes_value(true))
+        .arg(Arg::with_name("flatten")
+            .long("flatten")
+            .help("Also composite png and webp output over the --background color, leaving it fully opaque.")
+            .requires("background"))
+        .arg(Arg::with_name("write_gltf")
+            .long("write-gltf")
+            .help("Also write a .gltf to the output directory that uses the generated textures as KHR_materials_unlit materials.")
+            .conflicts_with_all(&["hdr"]))
+        .arg(Arg::with_name("atlas")
+            .long("atlas")
+            .help("Pack every generated texture into a single power of two atlas and print each material's rect as JSON.")
+            .conflicts_with_all(&["hdr", "png16", "write_gltf"]))
+        .arg(Arg::with_name("atlas_max_size")
+            .long("atlas-max-size")
+            .value_name("size")
+            .help("Maximum width and height of the atlas. Defaults to 4096.")
+            .requires("atlas")
+            .takes_value(true))
+        .arg(Arg::with_name("array")
+            .long("array")
+            .value_name("container")
+            .help("Write the textures of each size as the layers of one texture array, as numbered png layers or one ktx2 image, and print an index of each material's array and layer as JSON.")
+            .possible_values(&["png", "ktx2"])
+            .conflicts_with_all(&["format", "hdr", "png16", "mips", "atlas", "write_gltf", "manifest", "stats", "dry_run", "list"])
+            .takes_value(true))
+        .arg(Arg::with_name("force")
+            .long("force")
+            .help("Overwrite output files that already exist instead of failing."))
+        .arg(Arg::with_name("zip")
+            .long("zip")
+            .value_name("path")
+            .help("Write every texture into this zip archive instead of the output directory. The JSON output reports zip entry names.")
+            .conflicts_with_all(&["out", "write_gltf", "dry_run", "list"])
+            .takes_value(true))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .help("Keep running and regenerate an input whenever it or a texture it references changes on disk.")
+            .conflicts_with_all(&["zip", "dry_run", "list"]))
+        .arg(Arg::with_name("manifest")
+            .long("manifest")
+            .value_name("path")
+            .help("Write a JSON file describing each material's source textures and generated output, keyed by input path.")
+            .conflicts_with_all(&["dry_run", "atlas"])
+            .takes_value(true))
+        .arg(Arg::with_name("contact_sheet")
+            .long("contact-sheet")
+            .value_name("path")
+            .help("Write a png tiling a thumbnail of every generated texture, labeled with its material name, for reviewing them at a glance.")
+            .conflicts_with_all(&["atlas", "array", "only", "dry_run", "list"])
+            .takes_value(true))
+        .arg(Arg::with_name("name_map")
+            .long("name-map")
+            .value_name("path")
+            .help("JSON file mapping material names or indices to an output filename, or to an object with a filename, format, and baseColorFactor. Other materials are named as usual.")
+            .takes_value(true))
+        .arg(Arg::with_name("include")
+            .long("include")
+            .value_name("regex")
+            .help("Only generate materials whose name matches this pattern. Unnamed materials are skipped.")
+            .takes_value(true))
+        .arg(Arg::with_name("exclude")
+            .long("exclude")
+            .value_name("regex")
+            .help("Skip materials whose name matches this pattern.")
+            .takes_value(true))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .help("Resolve each material's maps and print what would be generated as JSON without writing any files."))
+        .arg(Arg::with_name("list")
+            .long("list")
+            .help("Print each material's name, alpha mode, textures, and factors as JSON without generating anything.")
+            .conflicts_with_all(&["dry_run", "atlas", "manifest", "write_gltf"]))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("Include the min, max, and mean of each channel of every texture, and the fraction of texels at 255, in the --json-verbose output. Implies --json-verbose.")
+            .conflicts_with_all(&["dry_run", "list", "atlas"]))
+        .arg(Arg::with_name("only")
+            .long("only")
+            .value_name("component")
+            .help("Write only these contributions of each material, each as its own texture suffixed with its name: the base color with its factor, the occlusion factor as gray, or the emissive map multiplied by its factor. Separate several with commas.")
+            .possible_values(&["base", "occlusion", "emissive"])
+            .multiple(true)
+            .require_delimiter(true)
+            .takes_value(true)
+            .conflicts_with_all(&["hdr", "png16", "mips", "atlas", "array", "write_gltf", "manifest", "stats", "dry_run", "list"]))
+        .arg(Arg::with_name("extract_source")
+            .long("extract-source")
+            .help("Also write the decoded base color, occlusion, and emissive images of each material next to its texture as <name>_source_<map>.png, such as the images embedded in a GLB.")
+            .conflicts_with_all(&["atlas", "array", "dry_run", "list"]))
+        .arg(Arg::with_name("json_verbose")
+            .long("json-verbose")
+            .help("Print an object per material with its index, name, output path, dimensions, format, and any error instead of only its output path."))
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .multiple(true)
+            .help("Log texture resolution and output paths. Repeat for more detail."))
+        .arg(Arg::with_name("sanity_check")
+            .long("sanity-check")
+            .help("Warn about occlusion and emissive maps that look like they are in each other's slot."))
+        .arg(Arg::with_name("strict")
+            .long("strict")
+            .help("Exit with status 1 if any material fails, like other errors. Without it failed materials are logged and printed as null, and the exit status is 2."))
+        .arg(Arg::with_name("quiet")
+            .long("quiet")
+            .help("Hide the progress bar and the summary printed after the run. The progress bar is always hidden when stdout is not a terminal."))
+        .arg(Arg::with_name("jobs")
+            .short("j")
+            .long("jobs")
+            .value_name("jobs")
+            .help("Maximum number of materials to process in parallel. Defaults to the number of CPUs.")
             .takes_value(true))
-        .get_matches();
+        .arg(Arg::with_name("config")
+            .long("config")
+            .value_name("path")
+            .help("TOML file of options named like their long flags, such as metal-darken = 0.5 or hdr = true. Flags on the command line override it.")
+            .takes_value(true));
+    let matches = app.clone().get_matches();
+    let cli_args = env::args_os().skip(1).collect::<Vec<_>>();
+    let (matches, config_result) = match matches.value_of("config").map(String::from) {
+        Some(path) => match read_config(&app, &matches, &cli_args, Path::new(&path)) {
+            Ok((args, warnings)) => {
+                // The options from the file go first so that the positional inputs stay last
+                let mut argv = env::args_os().take(1).collect::<Vec<_>>();
+                argv.extend(args.into_iter().map(From::from));
+                argv.extend(cli_args);
+                (app.get_matches_from(argv), Ok(warnings))
+            },
+            Err(e) => (matches, Err(e))
+        },
+        None => (matches, Ok(Vec::new()))
+    };
+    // clap can only make whole arguments conflict, not one value of --format
+    if matches.is_present("write_gltf") && matches.value_of("format") == Some("raw") {
+        clap::Error::with_description("The argument '--format raw' cannot be used with '--write-gltf', a glTF cannot reference raw RGBA8 bytes", ErrorKind::ArgumentConflict).exit();
+    }
 
-    match process_args(&matches) {
-        Ok(opts) => {
-            let results = opts.gltf.materials().map(|material| {
-                generate_unlit(&material, opts.gltf_dir, opts.lighten_factor).and_then(|img| {
-                    let filename = output_filename(&material);
-                    let path = opts.out_dir.join(filename);
-                    img.save(&path).map(|_| path).map_err(|e| From::from(e.description()))
-                })
-            });
-            let output = results.map(|path| {
-                match path {
-                    Ok(path) => JsonValue::String(String::from(path.to_str().unwrap())),
-                    Err(e) => {
-                        eprintln!("{}", e);
-                        JsonValue::Null
-                    }
-                }
-            });
-            println!("{}", JsonValue::Array(output.collect::<Vec<_>>()));
-            process::exit(0);
+    // Warnings and errors are always logged, RUST_LOG can still override the level
+    let level = match matches.occurrences_of("verbose") {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace
+    };
+    let mut logger = env_logger::Builder::new();
+    logger.filter_level(level)
+        .default_format_timestamp(false)
+        .default_format_module_path(false);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        logger.parse(&filters);
+    }
+    logger.init();
+
+    match config_result {
+        Ok(warnings) => for warning in warnings {
+            warn!("{}", warning);
         },
         Err(e) => {
-            eprintln!("Error: {}", e);
+            error!("{}", e);
             println!("{}", JsonValue::Null);
-            process::exit(1);
+            process::exit(EXIT_FATAL);
         }
-    };
-}
+    }
 
-fn output_filename(mat: &Material) -> String {
-    let extension = match mat.alpha_mode() {
-        AlphaMode::Opaque => "jpg",
-        _ => "png"
+    if let Err(e) = configure_jobs(&matches) {
+        error!("{}", e);
+        println!("{}", JsonValue::Null);
+        process::exit(EXIT_FATAL);
+    }
+
+    // Each input is processed independently. A single input prints its results
+    // directly, several print an object of results keyed by input path.
+    let inputs = matches.values_of("input").map(|inputs| inputs.collect::<Vec<_>>()).unwrap_or_default();
+    if inputs.is_empty() {
+        error!("A GLTF file must be provided.");
+        println!("{}", JsonValue::Null);
+        process::exit(EXIT_FATAL);
+    }
+    let strict = matches.is_present("strict");
+    let zip = match matches.value_of("zip") {
+        Some(path) => match expand_path(path).and_then(|path| ZipOutput::create(Path::new(&path), matches.is_present("force"))) {
+            Ok(zip) => Some(zip),
+            Err(e) => {
+                error!("Unable to create zip archive: {}", e);
+                println!("{}", JsonValue::Null);
+                process::exit(EXIT_FATAL);
+            }
+        },
+        None => None
     };
-    match mat.name() {
-        Some(name) => format!("{}_unlit.{}", name, extension),
-        None => format!("unlit_{}.{}", mat.index().unwrap(), extension)
+    let started = Instant::now();
+    let mut summary = Summary::default();
+    let mut failed = false;
+    let mut materials_failed = false;
+    let mut outputs = serde_json::Map::new();
+    let mut manifest = serde_json::Map::new();
+    let mut thumbnails = Vec::new();
+    for &input in &inputs {
+        let processed = expand_input(input).and_then(|path| process_args(&matches, &path, zip.as_ref()).and_then(|opts| run(&opts))).unwrap_or_else(|e| {
+            if inputs.len() == 1 {
+                error!("{}", e);
+            } else {
+                error!("{}: {}", input, e);
+            }
+            failed = true;
+            Processed { output: JsonValue::Null, manifest: JsonValue::Null, thumbnails: Vec::new(), output_failed: false, summary: Summary::default() }
+        });
+        if strict && processed.summary.failed > 0 {
+            if inputs.len() == 1 {
+                error!("{} materials failed.", processed.summary.failed);
+            } else {
+                error!("{}: {} materials failed.", input, processed.summary.failed);
+            }
+            failed = true;
+        }
+        failed |= processed.output_failed;
+        materials_failed |= processed.summary.failed > 0;
+        summary.add(&processed.summary);
+        outputs.insert(String::from(input), processed.output);
+        manifest.insert(String::from(input), processed.manifest);
+        thumbnails.extend(processed.thumbnails);
+    }
+    if let Some(zip) = zip {
+        if let Err(e) = zip.finish() {
+            error!("Unable to write zip archive: {}", e);
+            failed = true;
+        }
+    }
+    if let Some(path) = matches.value_of("manifest") {
+        if let Err(e) = write_manifest(Path::new(path), manifest) {
+            error!("Unable to write manifest: {}", e);
+            failed = true;
+        }
+    }
+    if let Some(path) = matches.value_of("contact_sheet") {
+        if let Err(e) = write_contact_sheet(Path::new(path), &thumbnails) {
+            error!("Unable to write contact sheet: {}", e);
+            failed = true;
+        }
     }
+    if !matches.is_present("quiet") && !matches.is_present("list") {
+        let elapsed = started.elapsed();
+        eprintln!("{} in {:.2}s", summary, elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9);
+    }
+    if inputs.len() == 1 {
+        println!("{}", outputs.remove(inputs[0]).unwrap());
+    } else {
+        println!("{}", JsonValue::Object(outputs));
+    }
+    if matches.is_present("watch") {
+        if let Err(e) = watch(&matches, &inputs) {
+            error!("Unable to watch for changes: {}", e);
+        }
+        process::exit(EXIT_FATAL);
+    }
+    process::exit(if failed {
+        EXIT_FATAL
+    } else if materials_failed {
+        EXIT_MATERIALS_FAILED
+    } else {
+        EXIT_OK
+    });
 }
 
-fn validate_dimensions<I: Iterator<Item=(u32, u32)>>(dimensions: I) -> Result<(u32, u32), Box<Error>> {
-    let mut candidate = None;
-    for d in dimensions {
-        if candidate.is_some() && candidate != Some(d) {
-            return Err(From::from(format!("Input map has inconsistent dimensions: {:?}", d)));
+/// How long --watch waits for saves to stop before regenerating.
+const WATCH_DELAY: Duration = Duration::from_millis(300);
+
+/// Regenerates each input whenever it or a file it references changes, until
+/// the process is interrupted. Each regeneration prints its JSON output and a
+/// line to stderr.
+fn watch(matches: &ArgMatches, inputs: &[&str]) -> Result<(), Box<Error>> {
+    if inputs.contains(&"-") {
+        return Err(From::from("stdin cannot be watched."));
+    }
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::watcher(tx, WATCH_DELAY)?;
+    let mut watched_dirs = HashSet::new();
+    let mut watched_files = Vec::new();
+    for &input in inputs {
+        let files = input_files(matches, input)?;
+        watch_dirs(&mut watcher, &mut watched_dirs, &files)?;
+        watched_files.push(files);
+    }
+    info!("Watching {} files for changes", watched_files.iter().map(|files| files.len()).sum::<usize>());
+
+    loop {
+        let changed = match rx.recv()? {
+            // Chmod includes touching a file to regenerate it
+            DebouncedEvent::Create(path) | DebouncedEvent::Write(path) | DebouncedEvent::Chmod(path) | DebouncedEvent::Rename(_, path) => path,
+            DebouncedEvent::Error(e, _) => return Err(Box::new(e)),
+            _ => continue
+        };
+        for (&input, files) in inputs.iter().zip(watched_files.iter_mut()) {
+            if !files.contains(&changed) {
+                continue;
+            }
+            let started = Instant::now();
+            match expand_input(input).and_then(|path| process_args(matches, &path, None).and_then(|opts| run(&opts))) {
+                Ok(processed) => {
+                    println!("{}", processed.output);
+                    let elapsed = started.elapsed();
+                    eprintln!("Regenerated {} after {} changed: {} in {:.2}s", input, changed.display(), processed.summary,
+                        elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9);
+                },
+                Err(e) => error!("{}: {}", input, e)
+            }
+            // The input may reference other files now. A glTF that cannot be read,
+            // such as one saved halfway, keeps watching the files it referenced.
+            if let Ok(input_files) = input_files(matches, input) {
+                watch_dirs(&mut watcher, &mut watched_dirs, &input_files)?;
+                *files = input_files;
+            }
         }
-        candidate = Some(d);
     }
-    candidate.ok_or(From::from("No input maps were provided."))
 }
 
-fn apply_occlusion(img: &mut RgbaImage, occlusion_map: &RgbImage, strength: f32) {
-    let multiplier = strength / 255.0;
-    for (mut pixel, occ) in img.pixels_mut().zip(occlusion_map.pixels()) {
-        // Occlusion is on the red channel of the occlusion texture
-        let occlusion_factor = occ[0] as f32 * multiplier;
-        pixel.data[0] = (pixel.data[0] as f32 * occlusion_factor) as u8;
-        pixel.data[1] = (pixel.data[1] as f32 * occlusion_factor) as u8;
-        pixel.data[2] = (pixel.data[2] as f32 * occlusion_factor) as u8;
+/// The absolute paths of an input glTF and every file its images are loaded from.
+fn input_files(matches: &ArgMatches, input: &str) -> Result<Vec<PathBuf>, Box<Error>> {
+    let path = expand_input(input)?;
+    let opts = process_args(matches, &path, None)?;
+    let cwd = env::current_dir()?;
+    let mut files = vec![cwd.join(&path)];
+    files.extend(referenced_files(&opts.gltf, opts.gltf_dir).into_iter().map(|file| cwd.join(file)));
+    Ok(files)
+}
+
+/// Watches the directory of each file, rather than the file itself, so files
+/// that editors save by replacing them are still seen.
+fn watch_dirs(watcher: &mut RecommendedWatcher, watched_dirs: &mut HashSet<PathBuf>, files: &[PathBuf]) -> Result<(), Box<Error>> {
+    for dir in files.iter().filter_map(|file| file.parent()) {
+        if watched_dirs.insert(dir.to_path_buf()) {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
     }
+    Ok(())
 }
 
-fn apply_emissive(img: &mut RgbaImage, emissive_map: &RgbImage, color: [f32; 3]) {
-    for (mut pixel, em) in img.pixels_mut().zip(emissive_map.pixels()) {
-        let emissive_r = ((em.data[0] as f32) * color[0]) as u8;
-        let emissive_g = ((em.data[1] as f32) * color[1]) as u8;
-        let emissive_b = ((em.data[2] as f32) * color[2]) as u8;
+/// What processing one input wrote, as JSON.
+struct Processed {
+    /// Printed to stdout.
+    output: JsonValue,
+    /// The entry of each material in the --manifest, null in modes without one.
+    manifest: JsonValue,
+    /// The label and thumbnail of each texture for the --contact-sheet, empty in
+    /// modes without one.
+    thumbnails: Vec<(String, RgbaImage)>,
+    /// Whether an output besides the textures, such as the --write-gltf glTF,
+    /// could not be written, which fails the run like an unwritable manifest.
+    output_failed: bool,
+    summary: Summary
+}
+
+/// Counts what a run did, printed to stderr once every input is processed.
+#[derive(Clone, Copy, Debug, Default)]
+struct Summary {
+    succeeded: usize,
+    /// How many materials failed, which --strict turns into a failed run.
+    failed: usize,
+    /// Materials left out by --include and --exclude.
+    skipped: usize,
+    /// The pixels of every written texture or atlas.
+    pixels: u64
+}
 
-        pixel.data[0] = pixel.data[0].saturating_add(emissive_r);
-        pixel.data[1] = pixel.data[1].saturating_add(emissive_g);
-        pixel.data[2] = pixel.data[2].saturating_add(emissive_b);
+impl Summary {
+    fn add(&mut self, other: &Summary) {
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+        self.pixels += other.pixels;
     }
 }
 
-fn generate_monocolor(w: u32, h: u32, color_factor: [f32; 4]) -> RgbaImage {
-    RgbaImage::from_pixel(w, h, Rgba::<u8>::from_channels(
-        (255.0 * color_factor[0]) as u8,
-        (255.0 * color_factor[1]) as u8,
-        (255.0 * color_factor[2]) as u8,
-        (255.0 * color_factor[3]) as u8
-    ))
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} materials processed: {} succeeded, {} failed, {} skipped, {} pixels written",
+            self.succeeded + self.failed, self.succeeded, self.failed, self.skipped, self.pixels)
+    }
 }
 
-fn generate_unlit(mat: &Material, gltf_dir: &Path, lighten_factor: f32) -> Result<RgbaImage, Box<Error>> {
-    let pbr = mat.pbr_metallic_roughness();
-    let base_texture = pbr.base_color_texture();
-    let base_color_factor = pbr.base_color_factor();
-    let base_map = base_texture.and_then(|info| load_if_exists(gltf_dir, &info.texture())).map(|i| i.to_rgba());
+/// Generates the textures of one input.
+fn run(opts: &Options) -> Result<Processed, Box<Error>> {
+    // Materials are independent, so each one is generated and saved on a worker thread.
+    // Collecting the indexed parallel iterator keeps the results in material order.
+    let material_count = opts.gltf.materials().count();
+    let materials = opts.gltf.materials().filter(|material| opts.filter.selects(material)).collect::<Vec<_>>();
+    let skipped = material_count - materials.len();
+    if skipped > 0 {
+        warn!("Skipped {} of {} materials.", skipped, material_count);
+    }
+    if opts.list {
+        let descriptions = materials.iter().map(|material| material_description(opts, material)).collect::<Vec<_>>();
+        let summary = Summary { succeeded: descriptions.len(), skipped, ..Summary::default() };
+        return Ok(Processed { output: JsonValue::Array(descriptions), manifest: JsonValue::Null, thumbnails: Vec::new(), output_failed: false, summary });
+    }
+    let filenames = output_filenames(opts, &materials);
+    if opts.dry_run {
+        let summaries = materials.par_iter().zip(filenames.par_iter())
+            .map(|(material, filename)| dry_run_summary(opts, material, filename))
+            .collect::<Vec<_>>();
+        let failed = summaries.iter().filter(|summary| !summary["error"].is_null()).count();
+        let summary = Summary { succeeded: summaries.len() - failed, failed, skipped, pixels: 0 };
+        return Ok(Processed { output: JsonValue::Array(summaries), manifest: JsonValue::Null, thumbnails: Vec::new(), output_failed: false, summary });
+    }
+    let progress = progress_bar(opts, materials.len());
+    if let Some(max_size) = opts.atlas {
+        return write_atlas(opts, &materials, max_size, &progress).map(|mut processed| {
+            processed.summary.skipped = skipped;
+            processed
+        });
+    }
+    if let Some(container) = opts.array {
+        return write_arrays(opts, &materials, container, &progress).map(|mut processed| {
+            processed.summary.skipped = skipped;
+            processed
+        });
+    }
+    if !opts.only.is_empty() {
+        return write_components(opts, &materials, &filenames, material_count, &progress).map(|mut processed| {
+            processed.summary.skipped = skipped;
+            processed
+        });
+    }
+    let results = materials.par_iter().zip(filenames.par_iter()).map(|(material, filename)| {
+        let material_json = &opts.document["materials"][material.index().unwrap()];
+        let format = opts.material_format(material);
+        let path = opts.out_dir.join(filename);
+        let saved = if let Err(e) = check_output(opts, &path) {
+            Err(e)
+        } else if format == OutputFormat::Hdr {
+            generate_unlit_hdr(material, material_json, &opts.textures, &opts.material_unlit(material))
+                .map_err(Box::from)
+                .and_then(|img| save_hdr(opts, &img, &path).map(|_| (img.dimensions(), None, linear_thumbnail(opts, &img))))
+                .and_then(|generated| save_sources(opts, material, &path).map(|_| generated))
+        } else if format == OutputFormat::Png16 {
+            generate_unlit_hdr(material, material_json, &opts.textures, &opts.material_unlit(material))
+                .map_err(Box::from)
+                .and_then(|img| save_png16(opts, &encode_srgb16(&img), &path).map(|_| (img.dimensions(), None, linear_thumbnail(opts, &img))))
+                .and_then(|generated| save_sources(opts, material, &path).map(|_| generated))
+        } else {
+            generate_unlit(material, material_json, &opts.textures, &opts.material_unlit(material)).map_err(Box::from).and_then(|mut img| {
+                let flattened = apply_background(opts, &mut img, format, is_premultiplied(opts, material));
+                if format == OutputFormat::Jpeg && !is_opaque(material) && !flattened {
+                    warn!("jpg output drops the alpha channel of transparent material {}.",
+                        material.name().unwrap_or("(unnamed)"));
+                    if opts.unlit.premultiply {
+                        warn!("Without an alpha channel the premultiplied colors of material {} are darkened.",
+                            material.name().unwrap_or("(unnamed)"));
+                    }
+                }
+                save_image(opts, &img, &path, format)
+                    .and_then(|_| save_mips(opts, &img, &path))
+                    .and_then(|_| save_sources(opts, material, &path))
+                    .map(|_| (img.dimensions(), if opts.stats { Some(texture_stats(&img)) } else { None },
+                        if opts.contact_sheet { Some(thumbnail(&img, CONTACT_SHEET_CELL)) } else { None }))
+            })
+        };
+        progress.inc(1);
+        saved.map(|((width, height), stats, thumbnail)| {
+            info!("Wrote {}", path.display());
+            Generated { path, width, height, stats, thumbnail }
+        }).map_err(|e| e.to_string())
+    }).collect::<Vec<_>>();
+    progress.finish_and_clear();
+    let mut output_failed = false;
+    if opts.write_gltf {
+        if let Err(e) = write_unlit_gltf(opts, &materials, &results) {
+            error!("Unable to write glTF: {}", e);
+            output_failed = true;
+        }
+    }
+    let manifest = materials.iter().zip(&results).map(|(material, result)| {
+        let mut entry = material_summary(opts, material, result);
+        entry["sources"] = material_sources(opts, material);
+        entry
+    }).collect::<Vec<_>>();
 
-    let occlusion_texture = mat.occlusion_texture();
-    let occlusion_strength = occlusion_texture.as_ref().map_or(0.0, |t| t.strength());
-    let occlusion_map = occlusion_texture.and_then(|info| load_if_exists(gltf_dir, &info.texture())).map(|i| i.to_rgb());
+    // The output stays indexed by material, with skipped materials left null
+    let mut output = vec![JsonValue::Null; material_count];
+    for (material, result) in materials.iter().zip(&results) {
+        if let Err(ref e) = *result {
+            error!("{}", e);
+        }
+        output[material.index().unwrap()] = if opts.json_verbose {
+            material_summary(opts, material, result)
+        } else {
+            match *result {
+                Ok(ref generated) => JsonValue::String(String::from(generated.path.to_str().unwrap())),
+                Err(_) => JsonValue::Null
+            }
+        };
+    }
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    let pixels = results.iter().filter_map(|result| result.as_ref().ok())
+        .map(|generated| generated.width as u64 * generated.height as u64)
+        .sum();
+    let summary = Summary { succeeded: results.len() - failed, failed, skipped, pixels };
+    let thumbnails = materials.iter().zip(results).filter_map(|(material, result)| {
+        let label = material.name().map_or_else(|| format!("material {}", material.index().unwrap()), String::from);
+        result.ok().and_then(|generated| generated.thumbnail).map(|thumbnail| (label, thumbnail))
+    }).collect();
+    Ok(Processed { output: JsonValue::Array(output), manifest: JsonValue::Array(manifest), thumbnails, output_failed, summary })
+}
 
-    let emissive_texture = mat.emissive_texture();
-    let emissive_factor = mat.emissive_factor();
-    let emissive_map = emissive_texture.and_then(|info| load_if_exists(gltf_dir, &info.texture())).map(|i| i.to_rgb());
+/// The size of each cell of the --contact-sheet, in pixels.
+const CONTACT_SHEET_CELL: u32 = 128;
 
-    let dimensions = [
-        base_map.as_ref().map(|i| i.dimensions()),
-        occlusion_map.as_ref().map(|i| i.dimensions()),
-        emissive_map.as_ref().map(|i| i.dimensions())
-    ];
-    let (w, h) = validate_dimensions(dimensions.into_iter().filter_map(|&m| m))?;
+/// A --contact-sheet thumbnail of a texture generated from the linear bake.
+fn linear_thumbnail(opts: &Options, img: &LinearImage) -> Option<RgbaImage> {
+    if opts.contact_sheet {
+        Some(thumbnail(&encode_srgb(img), CONTACT_SHEET_CELL))
+    } else {
+        None
+    }
+}
 
-    let lighten = (lighten_factor * 255.0) as u8;
+/// Tiles the thumbnails of every input into one png.
+fn write_contact_sheet(path: &Path, thumbnails: &[(String, RgbaImage)]) -> Result<(), Box<Error>> {
+    let cells = thumbnails.iter().map(|(label, thumbnail)| (label.as_str(), thumbnail)).collect::<Vec<_>>();
+    let sheet = contact_sheet(&cells, CONTACT_SHEET_CELL);
+    let (width, height) = sheet.dimensions();
+    encode_png(BufWriter::new(File::create(path)?), &sheet, width, height, ColorType::RGBA(8), true)?;
+    Ok(())
+}
 
-    // Set the unlit_map to the base color map if it exists
-    let mut unlit_map = base_map.map_or_else(|| generate_monocolor(w, h, base_color_factor), |mut base_map| {
-        for mut pixel in base_map.pixels_mut() {
-            pixel.data[0] = ((pixel.data[0] as f32 * base_color_factor[0]) as u8).saturating_add(lighten);
-            pixel.data[1] = ((pixel.data[1] as f32 * base_color_factor[1]) as u8).saturating_add(lighten);
-            pixel.data[2] = ((pixel.data[2] as f32 * base_color_factor[2]) as u8).saturating_add(lighten);
-            pixel.data[3] = (pixel.data[3] as f32 * base_color_factor[3]) as u8;
-        }
-        base_map
+/// Describes the texture generated for a material, or why it failed, for --json-verbose
+/// and --stats.
+fn material_summary(opts: &Options, material: &Material, result: &Result<Generated, String>) -> JsonValue {
+    let mut summary = json!({
+        "material": material.name(),
+        "index": material.index(),
+        "format": opts.material_format(material).extension()
     });
+    match *result {
+        Ok(Generated { ref path, width, height, ref stats, .. }) => {
+            summary["output"] = json!(path.to_str());
+            summary["width"] = json!(width);
+            summary["height"] = json!(height);
+            if let Some(ref stats) = *stats {
+                summary["stats"] = stats_json(stats);
+            }
+        },
+        Err(ref e) => {
+            summary["output"] = JsonValue::Null;
+            summary["error"] = json!(e);
+        }
+    }
+    summary
+}
+
+/// The statistics of each channel of a texture, keyed by channel.
+fn stats_json(stats: &TextureStats) -> JsonValue {
+    let mut channels = serde_json::Map::new();
+    for (i, channel) in ["r", "g", "b", "a"].iter().enumerate() {
+        channels.insert(String::from(*channel), json!({
+            "min": stats.min[i],
+            "max": stats.max[i],
+            "mean": stats.mean[i],
+            "saturated": stats.saturated[i]
+        }));
+    }
+    JsonValue::Object(channels)
+}
 
-    // Multiply the occlusion map if it exists
-    if let Some(occlusion_map) = occlusion_map {
-        apply_occlusion(&mut unlit_map, &occlusion_map, occlusion_strength);
+/// The uri of the image of a texture info, or `None` for images embedded in a
+/// buffer or data uri.
+fn texture_uri<'a>(opts: &'a Options, info_json: &JsonValue) -> Option<&'a str> {
+    let image = info_json["index"].as_u64().map(|index| &opts.document["textures"][index as usize]["source"]);
+    let uri = image.and_then(|image| image.as_u64()).and_then(|image| opts.document["images"][image as usize]["uri"].as_str());
+    uri.filter(|uri| !uri.starts_with("data:"))
+}
+
+/// The uri of the image of each texture a material references, or null for
+/// images embedded in a buffer or data uri.
+fn material_sources(opts: &Options, material: &Material) -> JsonValue {
+    let material_json = &opts.document["materials"][material.index().unwrap()];
+    let uri = |info_json: &JsonValue| json!(texture_uri(opts, info_json));
+    json!({
+        "baseColor": uri(base_color_texture_json(material_json)),
+        "occlusion": uri(&material_json["occlusionTexture"]),
+        "emissive": uri(&material_json["emissiveTexture"]),
+        "metallicRoughness": uri(&material_json["pbrMetallicRoughness"]["metallicRoughnessTexture"])
+    })
+}
+
+/// Describes a material's alpha mode, textures, and factors for --list. Each
+/// texture is null when the material has none.
+fn material_description(opts: &Options, material: &Material) -> JsonValue {
+    let material_json = &opts.document["materials"][material.index().unwrap()];
+    let texture = |info_json: &JsonValue| match info_json["index"].as_u64() {
+        Some(index) => json!({ "texture": index, "uri": texture_uri(opts, info_json) }),
+        None => JsonValue::Null
+    };
+    let alpha_mode = match material.alpha_mode() {
+        AlphaMode::Opaque => "OPAQUE",
+        AlphaMode::Mask => "MASK",
+        AlphaMode::Blend => "BLEND"
     };
+    let pbr = material.pbr_metallic_roughness();
+    json!({
+        "material": material.name(),
+        "index": material.index(),
+        "alphaMode": alpha_mode,
+        "alphaCutoff": material.alpha_cutoff(),
+        "textures": {
+            "baseColor": texture(base_color_texture_json(material_json)),
+            "occlusion": texture(&material_json["occlusionTexture"]),
+            "emissive": texture(&material_json["emissiveTexture"]),
+            "metallicRoughness": texture(&material_json["pbrMetallicRoughness"]["metallicRoughnessTexture"])
+        },
+        "factors": {
+            "baseColor": base_color_factor(material, material_json),
+            "emissive": material.emissive_factor(),
+            "metallic": pbr.metallic_factor(),
+            "roughness": pbr.roughness_factor(),
+            // Read from the JSON, as the gltf crate panics on an occlusion texture out of range
+            "occlusionStrength": material_json["occlusionTexture"].as_object().map(|occlusion| occlusion.get("strength").and_then(|strength| strength.as_f64()).unwrap_or(1.0))
+        }
+    })
+}
+
+/// Writes the manifest entries of every input, keyed by input path.
+fn write_manifest(path: &Path, manifest: serde_json::Map<String, JsonValue>) -> Result<(), Box<Error>> {
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &JsonValue::Object(manifest))?;
+    Ok(())
+}
+
+/// Generates every material and writes their textures packed into one atlas.
+/// Returns the atlas path and the rect of each material, or null for materials
+/// that failed to generate.
+fn write_atlas(opts: &Options, materials: &[Material], max_size: u32, progress: &ProgressBar) -> Result<Processed, Box<Error>> {
+    let mut images = materials.par_iter().map(|material| {
+        let material_json = &opts.document["materials"][material.index().unwrap()];
+        let img = generate_unlit(material, material_json, &opts.textures, &opts.material_unlit(material)).map_err(|e| e.to_string());
+        progress.inc(1);
+        img
+    }).collect::<Vec<_>>();
+    progress.finish_and_clear();
 
-    // Add the emissive map if it exists
-    if let Some(emissive_map) = emissive_map {
-        apply_emissive(&mut unlit_map, &emissive_map, emissive_factor);
+    // The atlas needs an alpha channel if any packed material is transparent
+    let transparent = materials.iter().zip(&images).any(|(material, img)| img.is_ok() && !is_opaque(material));
+    let format = match opts.format {
+        OutputFormat::Auto if transparent => OutputFormat::Png,
+        OutputFormat::Auto => OutputFormat::Jpeg,
+        format => format
     };
+    // Only transparent materials are premultiplied, so each texture is flattened
+    // before it is packed, and then the atlas for the space between them
+    for (material, img) in materials.iter().zip(&mut images) {
+        if let Ok(ref mut img) = *img {
+            apply_background(opts, img, format, is_premultiplied(opts, material));
+        }
+    }
+    let generated = images.iter().filter_map(|img| img.as_ref().ok()).collect::<Vec<_>>();
+    let mut atlas = pack_atlas(&generated, max_size)?;
+    let flattened = apply_background(opts, &mut atlas.image, format, false);
+    if format == OutputFormat::Jpeg && transparent && !flattened {
+        warn!("jpg output drops the alpha channel of transparent materials in the atlas.");
+    }
 
-    Ok(unlit_map)
+    let path = opts.out_dir.join(format!("{}_atlas.{}", opts.stem, format.extension()));
+    check_output(opts, &path)?;
+    save_image(opts, &atlas.image, &path, format)?;
+    info!("Wrote {}", path.display());
+
+    let size = atlas.image.width();
+    let failed = images.iter().filter(|img| img.is_err()).count();
+    let summary = Summary { succeeded: images.len() - failed, failed, skipped: 0, pixels: size as u64 * size as u64 };
+    let mut rects = atlas.rects.iter();
+    let materials = materials.iter().zip(images).map(|(material, img)| {
+        match img {
+            Ok(_) => {
+                let rect = rects.next().unwrap();
+                json!({
+                    "material": material.name(),
+                    "index": material.index(),
+                    "x": rect.x,
+                    "y": rect.y,
+                    "width": rect.width,
+                    "height": rect.height,
+                    "uv": rect.uv_bounds(size)
+                })
+            },
+            Err(e) => {
+                error!("{}", e);
+                JsonValue::Null
+            }
+        }
+    }).collect::<Vec<_>>();
+    let output = json!({ "atlas": path.to_str(), "materials": materials });
+    Ok(Processed { output, manifest: JsonValue::Null, thumbnails: Vec::new(), output_failed: false, summary })
 }
 
-fn load_if_exists(dir: &Path, texture: &Texture) -> Option<DynamicImage> {
-    let load_result = match texture.source().data() {
-        Data::Uri { uri, .. } => image::open(dir.join(uri)),
-        Data::View { .. } => Err(ImageError::FormatError(String::from("Images in data views not supported.")))
+/// Generates every material and writes the textures of each size as the layers
+/// of one texture array, in material order, along with a `{stem}_array.json`
+/// index of the arrays and the layer of every material. Returns the index, with
+/// null for materials that failed to generate.
+fn write_arrays(opts: &Options, materials: &[Material], container: Container, progress: &ProgressBar) -> Result<Processed, Box<Error>> {
+    let images = materials.par_iter().map(|material| {
+        let material_json = &opts.document["materials"][material.index().unwrap()];
+        let img = generate_unlit(material, material_json, &opts.textures, &opts.material_unlit(material)).map_err(|e| e.to_string());
+        progress.inc(1);
+        img
+    }).collect::<Vec<_>>();
+    progress.finish_and_clear();
+
+    // Arrays are numbered in the order their first material appears
+    let mut groups: Vec<((u32, u32), Vec<usize>)> = Vec::new();
+    for (i, img) in images.iter().enumerate() {
+        if let Ok(ref img) = *img {
+            let dimensions = img.dimensions();
+            match groups.iter_mut().find(|group| group.0 == dimensions) {
+                Some(group) => group.1.push(i),
+                None => groups.push((dimensions, vec![i]))
+            }
+        }
+    }
+
+    let mut layers = vec![JsonValue::Null; materials.len()];
+    let mut arrays = Vec::new();
+    let mut pixels = 0;
+    for (array, &((width, height), ref members)) in groups.iter().enumerate() {
+        let textures = members.iter().map(|&i| images[i].as_ref().unwrap()).collect::<Vec<_>>();
+        let files = match container {
+            Container::Png => {
+                let mut files = Vec::new();
+                for (layer, img) in textures.iter().enumerate() {
+                    let path = opts.out_dir.join(format!("{}_array{}_layer{}.png", opts.stem, array, layer));
+                    check_output(opts, &path)?;
+                    save_image(opts, img, &path, OutputFormat::Png)?;
+                    files.push(json!(path.to_str()));
+                }
+                files
+            },
+            Container::Ktx2 => {
+                let path = opts.out_dir.join(format!("{}_array{}.ktx2", opts.stem, array));
+                check_output(opts, &path)?;
+                let mut data = Vec::new();
+                encode_ktx2_array(&mut data, &textures)?;
+                write_output(opts, &path, &data)?;
+                vec![json!(path.to_str())]
+            }
+        };
+        info!("Wrote a {}x{} array of {} layers", width, height, members.len());
+        pixels += width as u64 * height as u64 * members.len() as u64;
+        for (layer, &i) in members.iter().enumerate() {
+            layers[i] = json!({ "array": array, "layer": layer });
+        }
+        arrays.push(json!({ "width": width, "height": height, "layers": members.len(), "files": files }));
+    }
+
+    let materials = materials.iter().zip(&images).zip(layers).map(|((material, img), layer)| {
+        match *img {
+            Ok(_) => json!({
+                "material": material.name(),
+                "index": material.index(),
+                "array": layer["array"],
+                "layer": layer["layer"]
+            }),
+            Err(ref e) => {
+                error!("{}", e);
+                JsonValue::Null
+            }
+        }
+    }).collect::<Vec<_>>();
+    let index_path = opts.out_dir.join(format!("{}_array.json", opts.stem));
+    check_output(opts, &index_path)?;
+    let index = json!({ "arrays": arrays, "materials": materials });
+    write_output(opts, &index_path, &serde_json::to_vec_pretty(&index)?)?;
+    info!("Wrote {}", index_path.display());
+
+    let failed = images.iter().filter(|img| img.is_err()).count();
+    let summary = Summary { succeeded: images.len() - failed, failed, skipped: 0, pixels };
+    let output = json!({ "index": index_path.to_str(), "arrays": index["arrays"], "materials": index["materials"] });
+    Ok(Processed { output, manifest: JsonValue::Null, thumbnails: Vec::new(), output_failed: false, summary })
+}
+
+/// Generates each `--only` component of every material and writes it next to
+/// where its unlit texture would go, suffixed with the component's name. Returns
+/// the path of each component by material index, or null for materials that
+/// failed or were skipped.
+fn write_components(opts: &Options, materials: &[Material], filenames: &[String], material_count: usize, progress: &ProgressBar) -> Result<Processed, Box<Error>> {
+    let results = materials.par_iter().zip(filenames.par_iter()).map(|(material, filename)| {
+        let material_json = &opts.document["materials"][material.index().unwrap()];
+        let format = opts.material_format(material);
+        let path = opts.out_dir.join(filename);
+        let stem = path.file_stem().and_then(|s| s.to_str()).ok_or("Invalid output path.").map_err(|e| e.to_string())?;
+        let mut paths = serde_json::Map::new();
+        let mut pixels = 0;
+        for &component in &opts.only {
+            let component_path = path.with_file_name(format!("{}_{}.{}", stem, component.name(), format.extension()));
+            let (width, height) = check_output(opts, &component_path)
+                .and_then(|_| generate_component(material, material_json, &opts.textures, &opts.material_unlit(material), component).map_err(Box::from))
+                .and_then(|mut img| {
+                    apply_background(opts, &mut img, format, component == Component::Base && is_premultiplied(opts, material));
+                    save_image(opts, &img, &component_path, format).map(|_| img.dimensions())
+                })
+                .map_err(|e| e.to_string())?;
+            info!("Wrote {}", component_path.display());
+            pixels += width as u64 * height as u64;
+            paths.insert(String::from(component.name()), json!(component_path.to_str()));
+        }
+        progress.inc(1);
+        Ok((JsonValue::Object(paths), pixels))
+    }).collect::<Vec<Result<_, String>>>();
+    progress.finish_and_clear();
+
+    let mut output = vec![JsonValue::Null; material_count];
+    for (material, result) in materials.iter().zip(&results) {
+        match *result {
+            Ok((ref paths, _)) => output[material.index().unwrap()] = paths.clone(),
+            Err(ref e) => error!("{}", e)
+        }
+    }
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    let pixels = results.iter().filter_map(|result| result.as_ref().ok()).map(|&(_, pixels)| pixels).sum();
+    let summary = Summary { succeeded: results.len() - failed, failed, skipped: 0, pixels };
+    Ok(Processed { output: JsonValue::Array(output), manifest: JsonValue::Null, thumbnails: Vec::new(), output_failed: false, summary })
+}
+
+/// Creates a bar counting generated materials. It is drawn to stderr, and only
+/// when stdout is a terminal so piped JSON output is never interleaved with it.
+fn progress_bar(opts: &Options, len: usize) -> ProgressBar {
+    if opts.quiet || !atty::is(atty::Stream::Stdout) {
+        return ProgressBar::hidden();
+    }
+    let progress = ProgressBar::new(len as u64);
+    progress.set_style(ProgressStyle::default_bar()
+        .template("[{elapsed_precise}] {bar:40} {pos}/{len} materials"));
+    progress
+}
+
+/// Describes the maps a material would be baked from and the file it would be
+/// written to.
+fn dry_run_summary(opts: &Options, material: &Material, filename: &str) -> JsonValue {
+    let index = material.index().unwrap();
+    let format = opts.material_format(material);
+    let mut summary = json!({
+        "material": material.name(),
+        "index": index,
+        "output": opts.out_dir.join(filename).to_str(),
+        "format": format.extension()
+    });
+    match plan_material(material, &opts.document["materials"][index], &opts.textures, &opts.material_unlit(material)) {
+        Ok(MaterialPlan { width, height, base_dimensions, occlusion_dimensions, emissive_dimensions, metallic_roughness_dimensions }) => {
+            summary["width"] = json!(width);
+            summary["height"] = json!(height);
+            summary["base"] = json!(base_dimensions);
+            summary["occlusion"] = json!(occlusion_dimensions);
+            summary["emissive"] = json!(emissive_dimensions);
+            summary["metallicRoughness"] = json!(metallic_roughness_dimensions);
+        },
+        Err(e) => summary["error"] = json!(e.to_string())
+    }
+    summary
+}
+
+/// Names the output of every material, from its `--name-map` entry if it has a
+/// filename. Names that would collide, ignoring case for case-insensitive
+/// filesystems, have the material index appended so no output overwrites another.
+fn output_filenames(opts: &Options, materials: &[Material]) -> Vec<String> {
+    let filenames = materials.iter().map(|material| {
+        match opts.name_map_entry(material).and_then(|entry| entry.filename.clone()) {
+            Some(filename) => filename,
+            None => output_filename(material, opts.material_format(material), opts.output_suffix)
+        }
+    }).collect::<Vec<_>>();
+    let keys = filenames.iter().map(|filename| filename.to_lowercase()).collect::<Vec<_>>();
+    filenames.iter().zip(materials).enumerate().map(|(i, (filename, material))| {
+        if keys.iter().filter(|&key| *key == keys[i]).count() == 1 {
+            return filename.clone();
+        }
+        let path = Path::new(filename);
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+        format!("{}_{}.{}", stem, material.index().unwrap(), extension)
+    }).collect()
+}
+
+/// Names the output of a material `{name}{suffix}` after sanitizing the name.
+/// Unnamed materials are named after the suffix and their index instead,
+/// `unlit_{index}` by default.
+fn output_filename(mat: &Material, format: OutputFormat, suffix: &str) -> String {
+    let extension = format.extension();
+    match mat.name().map(sanitize_name).filter(|name| !name.is_empty()) {
+        Some(name) => format!("{}{}.{}", name, suffix, extension),
+        None => {
+            let prefix = match suffix.trim_start_matches('_') {
+                "" => "unlit",
+                prefix => prefix
+            };
+            format!("{}_{}.{}", prefix, mat.index().unwrap(), extension)
+        }
+    }
+}
+
+/// Replaces path separators, characters reserved on common filesystems, and runs
+/// of whitespace in a material name with underscores.
+fn sanitize_name(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    let mut in_whitespace = false;
+    for c in name.trim().chars() {
+        if c.is_whitespace() {
+            if !in_whitespace {
+                sanitized.push('_');
+            }
+            in_whitespace = true;
+            continue;
+        }
+        in_whitespace = false;
+        match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => sanitized.push('_'),
+            c if c.is_control() => sanitized.push('_'),
+            c => sanitized.push(c)
+        }
+    }
+    sanitized
+}
+
+/// Fails instead of overwriting an existing output file unless `force` is set.
+fn check_overwrite(path: &Path, force: bool) -> Result<(), Box<Error>> {
+    if !force && path.exists() {
+        return Err(From::from(format!("{} already exists, use --force to overwrite it.", path.display())));
+    }
+    Ok(())
+}
+
+/// [`check_overwrite`] for an output, which never exists yet in a new --zip archive.
+fn check_output(opts: &Options, path: &Path) -> Result<(), Box<Error>> {
+    match opts.zip {
+        Some(_) => Ok(()),
+        None => check_overwrite(path, opts.force)
+    }
+}
+
+/// Writes an encoded output to its file, or to its entry of the --zip archive.
+fn write_output(opts: &Options, path: &Path, data: &[u8]) -> Result<(), Box<Error>> {
+    match opts.zip {
+        Some(zip) => zip.add(path, data),
+        None => {
+            fs::write(path, data)?;
+            Ok(())
+        }
+    }
+}
+
+fn save_image(opts: &Options, img: &RgbaImage, path: &Path, format: OutputFormat) -> Result<(), Box<Error>> {
+    let (w, h) = img.dimensions();
+    let mut data = Vec::new();
+    match format {
+        // image::save always encodes JPEG at its default quality
+        OutputFormat::Jpeg => {
+            JPEGEncoder::new_with_quality(&mut data, opts.encoding.jpeg_quality).encode(img, w, h, ColorType::RGBA(8))?;
+        },
+        // The image crate has no WebP encoder. WebP keeps the alpha channel either way.
+        OutputFormat::Webp => {
+            let encoder = webp::Encoder::from_rgba(img, w, h);
+            let webp = if opts.encoding.webp_lossless {
+                encoder.encode_lossless()
+            } else {
+                encoder.encode(opts.encoding.webp_quality)
+            };
+            data.extend_from_slice(&webp);
+        },
+        // Rows top to bottom of RGBA texels, the layout of RgbaImage
+        OutputFormat::Raw => {
+            data.extend_from_slice(img);
+            let dimensions = json!({ "width": w, "height": h, "channels": 4 });
+            write_output(opts, &path.with_extension("json"), &serde_json::to_vec_pretty(&dimensions)?)?;
+        },
+        // Encoded explicitly so a --name-map filename's extension cannot pick another format
+        OutputFormat::Png => {
+            encode_png(&mut data, img, w, h, ColorType::RGBA(8), opts.encoding.srgb_chunk)?;
+        },
+        // These are resolved or written from the linear bake before saving
+        OutputFormat::Auto | OutputFormat::Hdr | OutputFormat::Png16 => {
+            return Err(Box::new(UnlitError::InvalidOutput(format!("{:?} output cannot be saved from an 8-bit texture.", format))));
+        }
+    }
+    write_output(opts, path, &data)
+}
+
+/// Writes the mip chain of a texture saved at `path` next to it, if enabled.
+fn save_mips(opts: &Options, img: &RgbaImage, path: &Path) -> Result<(), Box<Error>> {
+    let mips = match opts.mips {
+        Some(mips) => mips,
+        None => return Ok(())
     };
-    match load_result {
-        Ok(img) => Some(img),
-        Err(e) => {
-            eprintln!("{}", e);
-            None
+    let levels = generate_mips(img.clone(), opts.mip_filter);
+    match mips {
+        Container::Png => {
+            let stem = path.file_stem().and_then(|s| s.to_str()).ok_or("Invalid output path.")?;
+            for (level, mip) in levels.iter().enumerate() {
+                let mip_path = path.with_file_name(format!("{}_mip{}.png", stem, level));
+                check_output(opts, &mip_path)?;
+                let mut data = Vec::new();
+                encode_png(&mut data, mip, mip.width(), mip.height(), ColorType::RGBA(8), opts.encoding.srgb_chunk)?;
+                write_output(opts, &mip_path, &data)?;
+                debug!("Wrote {}", mip_path.display());
+            }
+        },
+        Container::Ktx2 => {
+            let ktx2_path = path.with_extension("ktx2");
+            check_output(opts, &ktx2_path)?;
+            let mut data = Vec::new();
+            encode_ktx2(&mut data, &levels)?;
+            write_output(opts, &ktx2_path, &data)?;
+            debug!("Wrote {}", ktx2_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Writes the decoded base color, occlusion, and emissive images of a material
+/// whose texture is saved at `path` next to it as PNGs, if enabled.
+fn save_sources(opts: &Options, material: &Material, path: &Path) -> Result<(), Box<Error>> {
+    if !opts.extract_source {
+        return Ok(());
+    }
+    let material_json = &opts.document["materials"][material.index().unwrap()];
+    let stem = path.file_stem().and_then(|s| s.to_str()).ok_or("Invalid output path.")?;
+    let sources = [
+        ("baseColor", base_color_texture_json(material_json)),
+        ("occlusion", &material_json["occlusionTexture"]),
+        ("emissive", &material_json["emissiveTexture"])
+    ];
+    for &(map, info_json) in sources.iter() {
+        let img = match info_json["index"].as_u64().and_then(|index| opts.textures.load_index(index as usize)) {
+            Some((img, _)) => img,
+            None => continue
+        };
+        let source_path = path.with_file_name(format!("{}_source_{}.png", stem, map));
+        check_output(opts, &source_path)?;
+        let (w, h) = img.dimensions();
+        let mut data = Vec::new();
+        PNGEncoder::new(&mut data).encode(&img.raw_pixels(), w, h, img.color())?;
+        write_output(opts, &source_path, &data)?;
+        debug!("Wrote {}", source_path.display());
+    }
+    Ok(())
+}
+
+/// Writes a copy of the input glTF to the output directory where every material
+/// with a generated texture is replaced by a KHR_materials_unlit material using it.
+fn write_unlit_gltf(opts: &Options, materials: &[Material], results: &[Result<Generated, String>]) -> Result<PathBuf, Box<Error>> {
+    let mut document = opts.document.clone();
+    let stem = opts.stem;
+    let path = opts.out_dir.join(format!("{}_unlit.gltf", stem));
+    check_overwrite(&path, opts.force)?;
+
+    // A .glb binary chunk has no uri, so it is written out as a .bin next to the .gltf
+    for buffer in opts.gltf.buffers() {
+        let index = buffer.index();
+        if buffer.uri() == "#bin" {
+            let data = opts.textures.buffers()[index].as_ref().ok_or("The .glb binary chunk is missing.")?;
+            let bin_filename = format!("{}.bin", stem);
+            let bin_path = opts.out_dir.join(&bin_filename);
+            check_overwrite(&bin_path, opts.force)?;
+            fs::write(bin_path, data)?;
+            document["buffers"][index]["uri"] = JsonValue::String(bin_filename);
+        } else {
+            document["buffers"][index]["uri"] = JsonValue::String(rebase_uri(buffer.uri(), opts.gltf_dir, &opts.out_dir)?);
+        }
+    }
+    for (index, image) in opts.document["images"].as_array().into_iter().flat_map(|images| images.iter()).enumerate() {
+        if let Some(uri) = image["uri"].as_str() {
+            document["images"][index]["uri"] = JsonValue::String(rebase_uri(uri, opts.gltf_dir, &opts.out_dir)?);
+        }
+    }
+
+    let mut used_unlit = false;
+    let mut used_webp = false;
+    for (material, result) in materials.iter().zip(results) {
+        let index = material.index().unwrap();
+        let path = match *result {
+            Ok(ref generated) => &generated.path,
+            Err(_) => continue
+        };
+        let filename = path.file_name().and_then(|f| f.to_str()).ok_or("Invalid output file path.")?;
+        let image = push_json(&mut document, "images", json!({ "uri": filename }));
+        // WebP is only a glTF texture source through EXT_texture_webp. There is no
+        // fallback image, so the extension is also required.
+        let texture = if opts.material_format(material) == OutputFormat::Webp {
+            used_webp = true;
+            push_json(&mut document, "textures", json!({ "extensions": { "EXT_texture_webp": { "source": image } } }))
+        } else {
+            push_json(&mut document, "textures", json!({ "source": image }))
+        };
+
+        // The texture is baked in the base color texture's UV space, so its texCoord
+        // and KHR_texture_transform are kept
+        let mut base_color_texture = match *base_color_texture_json(&opts.document["materials"][index]) {
+            JsonValue::Object(ref info) => JsonValue::Object(info.clone()),
+            _ => json!({})
+        };
+        base_color_texture["index"] = json!(texture);
+
+        // Occlusion, emissive, and the base color factor are baked into the texture
+        let material = &mut document["materials"][index];
+        if let Some(material) = material.as_object_mut() {
+            material.remove("occlusionTexture");
+            material.remove("emissiveTexture");
+            material.remove("emissiveFactor");
+        }
+        // The diffuse texture of a specular glossiness material is now the base color texture
+        if let Some(extensions) = material["extensions"].as_object_mut() {
+            extensions.remove("KHR_materials_pbrSpecularGlossiness");
         }
+        // Fallback metallic and roughness factors for viewers without KHR_materials_unlit
+        material["pbrMetallicRoughness"] = json!({
+            "baseColorTexture": base_color_texture,
+            "metallicFactor": 0.0,
+            "roughnessFactor": 0.9
+        });
+        material["extensions"]["KHR_materials_unlit"] = json!({});
+        used_unlit = true;
     }
+
+    if used_unlit {
+        add_extension(&mut document, "extensionsUsed", "KHR_materials_unlit");
+    }
+    if used_webp {
+        add_extension(&mut document, "extensionsUsed", "EXT_texture_webp");
+        add_extension(&mut document, "extensionsRequired", "EXT_texture_webp");
+    }
+
+    serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), &document)?;
+    Ok(path)
+}
+
+/// Adds `extension` to the top level extension list `key` unless it is already in it.
+fn add_extension(document: &mut JsonValue, key: &str, extension: &str) {
+    let listed = document[key].as_array().is_some_and(|extensions| extensions.iter().any(|e| e == extension));
+    if !listed {
+        push_json(document, key, json!(extension));
+    }
+}
+
+/// Appends `value` to the top level array `key`, creating it if needed, and returns its index.
+fn push_json(document: &mut JsonValue, key: &str, value: JsonValue) -> usize {
+    if !document[key].is_array() {
+        document[key] = JsonValue::Array(Vec::new());
+    }
+    let array = document[key].as_array_mut().unwrap();
+    array.push(value);
+    array.len() - 1
+}
+
+/// Rewrites a uri relative to `from_dir` so that it resolves the same from `to_dir`.
+fn rebase_uri(uri: &str, from_dir: &Path, to_dir: &Path) -> Result<String, Box<Error>> {
+    if uri.starts_with("data:") || uri.contains("://") || Path::new(uri).is_absolute() {
+        return Ok(String::from(uri));
+    }
+    let target = canonicalize_dir(from_dir)?.join(uri);
+    let to_dir = canonicalize_dir(to_dir)?;
+    let target = target.components().collect::<Vec<_>>();
+    let to_dir = to_dir.components().collect::<Vec<_>>();
+    let common = target.iter().zip(to_dir.iter()).take_while(|&(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..to_dir.len() {
+        relative.push("..");
+    }
+    for component in &target[common..] {
+        relative.push(component.as_os_str());
+    }
+    Ok(relative.to_string_lossy().replace('\\', "/"))
+}
+
+fn canonicalize_dir(dir: &Path) -> Result<PathBuf, Box<Error>> {
+    // The parent of a bare filename is an empty path
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    Ok(fs::canonicalize(dir)?)
+}
+
+fn save_hdr(opts: &Options, img: &LinearImage, path: &Path) -> Result<(), Box<Error>> {
+    // Radiance HDR has no alpha channel
+    let (w, h) = img.dimensions();
+    let pixels = img.pixels().map(|p| Rgb { data: [p.data[0], p.data[1], p.data[2]] }).collect::<Vec<_>>();
+    let mut data = Vec::new();
+    HDREncoder::new(&mut data).encode(&pixels, w as usize, h as usize)?;
+    write_output(opts, path, &data)
+}
+
+fn save_png16(opts: &Options, img: &Rgba16Image, path: &Path) -> Result<(), Box<Error>> {
+    let mut data = Vec::new();
+    encode_png16(&mut data, img, opts.encoding.srgb_chunk)?;
+    write_output(opts, path, &data)
 }
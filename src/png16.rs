@@ -0,0 +1,85 @@
+//! Decodes and encodes PNGs with 16 bits per channel, which the image crate
+//! cannot represent as a `DynamicImage`. Its decoder truncates them to the high
+//! byte of every channel instead.
+//!
+//! Maps are composited at 8 bits per channel, so 16-bit maps are rounded to the
+//! nearest 8-bit value when they are decoded. The linear bake keeps its floating
+//! point precision and can be written back out with [`encode_png16`].
+//!
+//! Both that and [`encode_png`] can tag a PNG with an sRGB chunk, which
+//! `PNGEncoder` never writes, so viewers do not mistake its colors for linear.
+
+use std::io::{self, Write};
+
+use image::{ColorType, DynamicImage, ImageBuffer, ImageError};
+use png::{self, HasParameters};
+
+use {Rgba16Image, UnlitError};
+
+/// The sRGB chunk's rendering intent. Perceptual is what untagged images are
+/// usually assumed to use.
+const PERCEPTUAL: u8 = 0;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Returns true if `data` is a PNG with 16 bits per channel.
+pub fn is_png16(data: &[u8]) -> bool {
+    // The first chunk is always IHDR, whose bit depth follows the width and height
+    data.starts_with(&PNG_SIGNATURE) && data.get(12..16) == Some(b"IHDR") && data.get(24) == Some(&16)
+}
+
+/// Rounds a 16-bit channel value to the nearest 8-bit value.
+fn narrow(value: u16) -> u8 {
+    ((value as u32 * 255 + 32767) / 65535) as u8
+}
+
+/// Decodes a 16-bit PNG, rounding every channel to 8 bits.
+pub fn decode_png16(data: &[u8]) -> Result<DynamicImage, UnlitError> {
+    let mut decoder = png::Decoder::new(data);
+    // Keep all 16 bits. There are no 16-bit palettes to expand, and png reports
+    // expanded 16-bit images as 8-bit.
+    decoder.set(png::Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info().map_err(ImageError::from)?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).map_err(ImageError::from)?;
+    let (color, bits) = reader.output_color_type();
+    let color = ColorType::from((color, bits));
+
+    // Samples are big endian byte pairs
+    let samples = buf.chunks(2).map(|pair| narrow((pair[0] as u16) << 8 | pair[1] as u16)).collect::<Vec<_>>();
+    debug!("Rounding {:?} PNG to 8 bits per channel", color);
+    let img = match color {
+        ColorType::Gray(16) => ImageBuffer::from_raw(info.width, info.height, samples).map(DynamicImage::ImageLuma8),
+        ColorType::GrayA(16) => ImageBuffer::from_raw(info.width, info.height, samples).map(DynamicImage::ImageLumaA8),
+        ColorType::RGB(16) => ImageBuffer::from_raw(info.width, info.height, samples).map(DynamicImage::ImageRgb8),
+        ColorType::RGBA(16) => ImageBuffer::from_raw(info.width, info.height, samples).map(DynamicImage::ImageRgba8),
+        _ => None
+    };
+    img.ok_or(UnlitError::ImageDecode(ImageError::UnsupportedColor(color)))
+}
+
+/// Writes `data` as a PNG like `PNGEncoder::encode`, with an sRGB chunk if `srgb`
+/// is set.
+pub fn encode_png<W: Write>(w: W, data: &[u8], width: u32, height: u32, color: ColorType, srgb: bool) -> io::Result<()> {
+    let (color, bits) = color.into();
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder.set(color).set(bits);
+    let mut writer = encoder.write_header()?;
+    // Ancillary chunks describing color must come before the image data
+    if srgb {
+        writer.write_chunk(*b"sRGB", &[PERCEPTUAL])?;
+    }
+    writer.write_image_data(data).map_err(io::Error::from)
+}
+
+/// Writes `img` as a PNG with 16 bits per channel, with an sRGB chunk if `srgb`
+/// is set.
+pub fn encode_png16<W: Write>(w: W, img: &Rgba16Image, srgb: bool) -> io::Result<()> {
+    let (width, height) = img.dimensions();
+    let mut bytes = Vec::with_capacity(img.len() * 2);
+    for &value in img.iter() {
+        bytes.push((value >> 8) as u8);
+        bytes.push(value as u8);
+    }
+    encode_png(w, &bytes, width, height, ColorType::RGBA(16), srgb)
+}
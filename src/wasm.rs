@@ -0,0 +1,40 @@
+//! A wasm-bindgen wrapper around [`generate_all_from_bytes`] for baking unlit
+//! textures in the browser, where there is no filesystem to read textures from.
+
+use js_sys::{Array, Function, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use {generate_all_from_bytes, UnlitFormat, UnlitOptions, UnlitOutput};
+
+/// Generates the unlit texture of every material of a .gltf or .glb as PNG
+/// bytes, in material order, with null for materials that failed.
+///
+/// `texture_resolver` is called with the uri of each external image and buffer,
+/// and returns its contents as a `Uint8Array`, or null if there are none.
+/// Textures are baked with the defaults of the command line tool.
+#[wasm_bindgen]
+pub fn generate_unlit_bytes(gltf_bytes: &[u8], texture_resolver: &Function) -> Result<Array, JsValue> {
+    let resolve = |uri: &str| {
+        let data = texture_resolver.call1(&JsValue::NULL, &JsValue::from_str(uri)).ok()?;
+        if data.is_null() || data.is_undefined() {
+            return None;
+        }
+        Some(Uint8Array::new(&data).to_vec())
+    };
+    let outputs = generate_all_from_bytes(gltf_bytes, resolve, &UnlitOptions::builder().build())
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let pngs = Array::new();
+    for output in outputs {
+        // PNG keeps the alpha channel, so every material is written as one
+        let png = output.and_then(|output| UnlitOutput { format: UnlitFormat::Png, ..output }.encode(100));
+        match png {
+            Ok(data) => pngs.push(&Uint8Array::from(&data[..])),
+            Err(e) => {
+                warn!("{}", e);
+                pngs.push(&JsValue::NULL)
+            }
+        };
+    }
+    Ok(pngs)
+}
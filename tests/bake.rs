@@ -0,0 +1,440 @@
+extern crate gltf;
+extern crate gltf_unlit_generator;
+extern crate image;
+extern crate serde_json;
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use gltf::Gltf;
+use gltf_unlit_generator::{generate_all, generate_all_from_bytes, generate_component, generate_unlit, premultiply_alpha, Component, load_buffers, validate_gltf, Reconcile, ResizeFilter, TextureCache, UnlitError, UnlitFormat, UnlitOptions};
+use image::RgbaImage;
+use serde_json::Value as JsonValue;
+
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// Bakes the first material of a fixture.
+fn bake_fixture(name: &str, opts: &UnlitOptions) -> Result<RgbaImage, UnlitError> {
+    bake_fixture_component(name, opts, None)
+}
+
+/// Bakes the first material of a fixture, or only one component of it.
+fn bake_fixture_component(name: &str, opts: &UnlitOptions, component: Option<Component>) -> Result<RgbaImage, UnlitError> {
+    let dir = fixtures_dir();
+    let data = fs::read(dir.join(name)).unwrap();
+    let gltf = validate_gltf(Gltf::from_slice(&data).unwrap()).unwrap();
+    let document: JsonValue = serde_json::from_slice(&data).unwrap();
+    let textures = TextureCache::new(&gltf, &dir, load_buffers(&gltf, &dir, None));
+    let material = gltf.materials().next().unwrap();
+    match component {
+        Some(component) => generate_component(&material, &document["materials"][0], &textures, opts, component),
+        None => generate_unlit(&material, &document["materials"][0], &textures, opts)
+    }
+}
+
+fn pixels(img: &RgbaImage) -> Vec<[u8; 4]> {
+    img.pixels().map(|pixel| pixel.data).collect()
+}
+
+#[test]
+fn multiplies_base_color_factor() {
+    let img = bake_fixture("base_only.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 50, 50, 255],
+        [0, 0, 0, 255],
+        [255, 127, 255, 255],
+        [128, 64, 128, 128]
+    ]);
+}
+
+#[test]
+fn overrides_base_color_factor() {
+    let opts = UnlitOptions { base_color_factor: Some([1.0, 1.0, 1.0, 0.5]), ..UnlitOptions::default() };
+    let img = bake_fixture("base_only.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 100, 50, 127],
+        [0, 0, 0, 127],
+        [255, 255, 255, 127],
+        [128, 128, 128, 64]
+    ]);
+}
+
+#[test]
+fn lightens_each_channel_separately() {
+    let opts = UnlitOptions { lighten_factor: [0.1, 0.0, 0.2], ..UnlitOptions::default() };
+    let img = bake_fixture("base_only.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [225, 50, 101, 255],
+        [25, 0, 51, 255],
+        [255, 127, 255, 255],
+        [153, 64, 179, 128]
+    ]);
+}
+
+#[test]
+fn generates_every_material_in_memory() {
+    let dir = fixtures_dir();
+    let data = fs::read(dir.join("base_emissive.gltf")).unwrap();
+    let gltf = validate_gltf(Gltf::from_slice(&data).unwrap()).unwrap();
+    let document: JsonValue = serde_json::from_slice(&data).unwrap();
+    let textures = TextureCache::new(&gltf, &dir, load_buffers(&gltf, &dir, None));
+    let outputs = generate_all(&gltf, &document, &textures, &UnlitOptions::default());
+    assert_eq!(outputs.len(), 1);
+    let output = outputs[0].as_ref().unwrap();
+    assert_eq!(output.material, 0);
+    assert_eq!(output.name, Some(String::from("base_emissive")));
+    assert_eq!(output.format, UnlitFormat::Jpeg);
+    let img = bake_fixture("base_emissive.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&output.image), pixels(&img));
+}
+
+#[test]
+fn generates_from_bytes_with_a_resolver() {
+    let dir = fixtures_dir();
+    let data = fs::read(dir.join("base_emissive.gltf")).unwrap();
+    let mut resolved = Vec::new();
+    let outputs = generate_all_from_bytes(&data, |uri| {
+        resolved.push(String::from(uri));
+        fs::read(dir.join(uri)).ok()
+    }, &UnlitOptions::default()).unwrap();
+    assert_eq!(resolved, vec!["base.png", "emissive.png"]);
+    let img = bake_fixture("base_emissive.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&outputs[0].as_ref().unwrap().image), pixels(&img));
+    assert!(generate_all_from_bytes(b"{", |_| None, &UnlitOptions::default()).is_err());
+}
+
+#[test]
+fn passes_through_a_lone_base_color_map() {
+    let img = bake_fixture("base_passthrough.gltf", &UnlitOptions::default()).unwrap();
+    let base = image::open(fixtures_dir().join("base.png")).unwrap().to_rgba();
+    assert_eq!(img.into_raw(), base.into_raw());
+}
+
+#[test]
+fn decodes_percent_encoded_uris() {
+    let img = bake_fixture("percent_encoded.gltf", &UnlitOptions::default()).unwrap();
+    let base = image::open(fixtures_dir().join("base color.png")).unwrap().to_rgba();
+    assert_eq!(img.into_raw(), base.into_raw());
+}
+
+#[test]
+fn writes_linear_output_however_it_was_composited() {
+    for &linear in &[false, true] {
+        let opts = UnlitOptions { linear, output_linear: true, ..UnlitOptions::default() };
+        let img = bake_fixture("base_passthrough.gltf", &opts).unwrap();
+        assert_eq!(pixels(&img), vec![
+            [147, 32, 8, 255],
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+            [55, 55, 55, 128]
+        ]);
+    }
+}
+
+#[test]
+fn reads_specular_glossiness_diffuse_as_base_color() {
+    let opts = UnlitOptions { metal_darken: 1.0, ..UnlitOptions::default() };
+    let img = bake_fixture("specular_glossiness.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 50, 50, 255],
+        [0, 0, 0, 255],
+        [255, 127, 255, 255],
+        [128, 64, 128, 128]
+    ]);
+}
+
+#[test]
+fn flips_the_finished_texture_vertically() {
+    let opts = UnlitOptions { flip_v: true, ..UnlitOptions::default() };
+    let img = bake_fixture("base_emissive.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [255, 255, 255, 255],
+        [128, 128, 128, 128],
+        [210, 110, 50, 255],
+        [255, 0, 0, 255]
+    ]);
+}
+
+#[test]
+fn darkens_by_occlusion_red_channel() {
+    let img = bake_fixture("base_occlusion.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [0, 0, 0, 255],
+        [32, 32, 32, 128]
+    ]);
+}
+
+#[test]
+fn floors_occlusion_regardless_of_strength() {
+    let opts = UnlitOptions { ao_floor: 0.5, ..UnlitOptions::default() };
+    let img = bake_fixture("base_occlusion.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [127, 127, 127, 255],
+        [64, 64, 64, 128]
+    ]);
+}
+
+#[test]
+fn curves_occlusion_by_ao_gamma() {
+    let opts = UnlitOptions { ao_gamma: 2.0, ..UnlitOptions::default() };
+    let softened = pixels(&bake_fixture("base_occlusion.gltf", &opts).unwrap());
+    let opts = UnlitOptions { ao_gamma: 0.5, ..UnlitOptions::default() };
+    let deepened = pixels(&bake_fixture("base_occlusion.gltf", &opts).unwrap());
+    assert_eq!(softened, vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [0, 0, 0, 255],
+        [64, 64, 64, 128]
+    ]);
+    assert_eq!(deepened, vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [0, 0, 0, 255],
+        [8, 8, 8, 128]
+    ]);
+}
+
+#[test]
+fn multiplies_extra_occlusion() {
+    let extra = image::open(fixtures_dir().join("occlusion.png")).unwrap().to_rgb();
+    let opts = UnlitOptions { extra_occlusion: Some(Arc::new(extra)), ..UnlitOptions::default() };
+    let img = bake_fixture("base_occlusion.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [0, 0, 0, 255],
+        [8, 8, 8, 128]
+    ]);
+
+    let extra = image::open(fixtures_dir().join("occlusion_4x4.png")).unwrap().to_rgb();
+    let opts = UnlitOptions { extra_occlusion: Some(Arc::new(extra)), ..UnlitOptions::default() };
+    match bake_fixture("base_occlusion.gltf", &opts) {
+        Err(UnlitError::DimensionMismatch { expected: (2, 2), found: (4, 4) }) => {},
+        result => panic!("expected a dimension mismatch, got {:?}", result.map(|img| img.dimensions()))
+    }
+}
+
+#[test]
+fn tiles_match_a_single_pass() {
+    let opts = UnlitOptions { tile_height: Some(1), ..UnlitOptions::default() };
+    for name in &["base_emissive.gltf", "dimension_mismatch.gltf"] {
+        let whole = bake_fixture(name, &UnlitOptions::default()).unwrap();
+        let tiled = bake_fixture(name, &opts).unwrap();
+        assert_eq!(pixels(&tiled), pixels(&whole));
+    }
+}
+
+#[test]
+fn adds_tinted_emissive() {
+    let img = bake_fixture("base_emissive.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [210, 110, 50, 255],
+        [255, 0, 0, 255],
+        [255, 255, 255, 255],
+        [128, 128, 128, 128]
+    ]);
+}
+
+#[test]
+fn adds_a_flat_emissive_factor_without_an_emissive_map() {
+    let img = bake_fixture("flat_emissive.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [251, 100, 50, 255],
+        [51, 0, 0, 255],
+        [255, 255, 255, 255],
+        [179, 128, 128, 128]
+    ]);
+}
+
+#[test]
+fn ignores_the_emissive_factor_of_unlit_and_solid_materials() {
+    // An unlit material's base color is its final color
+    let img = bake_fixture("unlit_emissive.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [100, 50, 25, 255],
+        [0, 0, 0, 255],
+        [127, 127, 127, 255],
+        [64, 64, 64, 128]
+    ]);
+    let opts = UnlitOptions { solid: true, default_size: Some(1), ..UnlitOptions::default() };
+    let img = bake_fixture("unlit_emissive.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![[127, 127, 127, 255]]);
+}
+
+#[test]
+fn rejects_mismatched_dimensions() {
+    match bake_fixture("emissive_mismatch.gltf", &UnlitOptions::default()) {
+        Err(UnlitError::DimensionMismatch { expected: (2, 2), found: (4, 4) }) => {},
+        result => panic!("unexpected result: {:?}", result.map(|img| img.dimensions()))
+    }
+}
+
+#[test]
+fn samples_occlusion_of_a_different_size() {
+    let img = bake_fixture("dimension_mismatch.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [255, 255, 255, 255],
+        [128, 128, 128, 128]
+    ]);
+}
+
+#[test]
+fn resizes_mismatched_dimensions_to_the_largest_map() {
+    let opts = UnlitOptions { resize: Some(ResizeFilter::Nearest), ..UnlitOptions::default() };
+    let img = bake_fixture("dimension_mismatch.gltf", &opts).unwrap();
+    assert_eq!(img.dimensions(), (4, 4));
+    assert_eq!(img.get_pixel(0, 0).data, [200, 100, 50, 255]);
+}
+
+#[test]
+fn falls_back_to_monocolor_base_color_factor() {
+    match bake_fixture("monocolor.gltf", &UnlitOptions::default()) {
+        Err(UnlitError::MissingTexture) => {},
+        result => panic!("unexpected result: {:?}", result.map(|img| img.dimensions()))
+    }
+
+    let opts = UnlitOptions { default_size: Some(2), ..UnlitOptions::default() };
+    let img = bake_fixture("monocolor.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![[255, 127, 0, 255]; 4]);
+}
+
+#[test]
+fn ignores_maps_when_solid() {
+    let opts = UnlitOptions { solid: true, default_size: Some(2), ..UnlitOptions::default() };
+    let img = bake_fixture("base_only.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![[255, 127, 255, 255]; 4]);
+}
+
+#[cfg(feature = "ktx2")]
+#[test]
+fn decodes_ktx2_base_color() {
+    let img = bake_fixture("ktx2.gltf", &UnlitOptions::default()).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [255, 255, 255, 255],
+        [128, 128, 128, 128]
+    ]);
+}
+
+#[cfg(not(feature = "ktx2"))]
+#[test]
+fn skips_ktx2_without_the_feature() {
+    match bake_fixture("ktx2.gltf", &UnlitOptions::default()) {
+        Err(UnlitError::MissingTexture) => {},
+        result => panic!("unexpected result: {:?}", result.map(|img| img.dimensions()))
+    }
+}
+
+#[test]
+fn reads_occlusion_and_metalness_from_a_packed_orm_texture() {
+    let opts = UnlitOptions { metal_darken: 0.5, ..UnlitOptions::default() };
+    let img = bake_fixture("orm.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [127, 127, 127, 255],
+        [128, 128, 128, 255],
+        [0, 0, 0, 255],
+        [191, 191, 191, 255]
+    ]);
+}
+
+#[test]
+fn encodes_identical_bytes_every_run() {
+    let dir = fixtures_dir();
+    let hashes = (0..2).map(|_| {
+        let data = fs::read(dir.join("base_emissive.gltf")).unwrap();
+        let gltf = validate_gltf(Gltf::from_slice(&data).unwrap()).unwrap();
+        let document: JsonValue = serde_json::from_slice(&data).unwrap();
+        let textures = TextureCache::new(&gltf, &dir, load_buffers(&gltf, &dir, None));
+        generate_all(&gltf, &document, &textures, &UnlitOptions::default()).into_iter().map(|output| {
+            let mut hasher = DefaultHasher::new();
+            output.unwrap().encode(90).unwrap().hash(&mut hasher);
+            hasher.finish()
+        }).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+    assert_eq!(hashes[0], hashes[1]);
+}
+
+#[test]
+fn reports_out_of_range_textures_as_missing() {
+    match bake_fixture("out_of_range.gltf", &UnlitOptions::default()) {
+        Err(UnlitError::MissingTexture) => {},
+        result => panic!("unexpected result: {:?}", result.map(|img| img.dimensions()))
+    }
+}
+
+#[test]
+fn overrides_occlusion_strength() {
+    // Half strength halves how much each texel is darkened
+    let opts = UnlitOptions { occlusion_strength: Some(0.5), ..UnlitOptions::default() };
+    let img = bake_fixture("base_occlusion.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), vec![
+        [200, 100, 50, 255],
+        [0, 0, 0, 255],
+        [127, 127, 127, 255],
+        [80, 80, 80, 128]
+    ]);
+    let default = bake_fixture("base_occlusion.gltf", &UnlitOptions::default()).unwrap();
+    assert!(pixels(&img).iter().zip(pixels(&default)).all(|(softened, full)| softened.iter().zip(&full).all(|(s, f)| s >= f)));
+
+    // Clamped to 1.0, the strength of the material
+    let opts = UnlitOptions { occlusion_strength: Some(2.0), ..UnlitOptions::default() };
+    let img = bake_fixture("base_occlusion.gltf", &opts).unwrap();
+    assert_eq!(pixels(&img), pixels(&bake_fixture("base_occlusion.gltf", &UnlitOptions::default()).unwrap()));
+}
+
+#[test]
+fn crops_or_pads_mismatched_dimensions() {
+    let opts = UnlitOptions { reconcile: Some(Reconcile::Crop), ..UnlitOptions::default() };
+    let img = bake_fixture("emissive_mismatch.gltf", &opts).unwrap();
+    // The center 2x2 texels of the 4x4 emissive map
+    assert_eq!(pixels(&img), vec![
+        [255, 100, 50, 255],
+        [255, 0, 0, 255],
+        [255, 255, 255, 255],
+        [255, 128, 128, 128]
+    ]);
+
+    let opts = UnlitOptions { reconcile: Some(Reconcile::Pad), ..UnlitOptions::default() };
+    let img = bake_fixture("emissive_mismatch.gltf", &opts).unwrap();
+    assert_eq!(img.dimensions(), (4, 4));
+    // The base color map is centered, padded with transparent texels the emissive map adds to
+    assert_eq!(img.get_pixel(1, 1).data, [255, 100, 50, 255]);
+    assert_eq!(img.get_pixel(2, 2).data, [255, 128, 128, 128]);
+    assert_eq!(img.get_pixel(0, 0).data, [255, 0, 0, 0]);
+}
+
+#[test]
+fn bakes_components_separately() {
+    let opts = UnlitOptions::default();
+    let base = bake_fixture_component("base_emissive.gltf", &opts, Some(Component::Base)).unwrap();
+    let emissive = bake_fixture_component("base_emissive.gltf", &opts, Some(Component::Emissive)).unwrap();
+    let occlusion = bake_fixture_component("base_occlusion.gltf", &opts, Some(Component::Occlusion)).unwrap();
+    assert_eq!(pixels(&base), pixels(&image::open(fixtures_dir().join("base.png")).unwrap().to_rgba()));
+    assert_eq!(pixels(&emissive), vec![[10, 10, 0, 255], [255, 0, 0, 255], [100, 50, 0, 255], [0, 0, 0, 255]]);
+    // The red channel of the occlusion map, in every channel
+    assert_eq!(pixels(&occlusion), vec![[255, 255, 255, 255], [128, 128, 128, 255], [0, 0, 0, 255], [64, 64, 64, 255]]);
+}
+
+#[test]
+fn premultiplies_after_occlusion_and_emissive() {
+    let straight = bake_fixture("blend_occlusion_emissive.gltf", &UnlitOptions::default()).unwrap();
+    let opts = UnlitOptions { premultiply: true, ..UnlitOptions::default() };
+    let premultiplied = bake_fixture("blend_occlusion_emissive.gltf", &opts).unwrap();
+    let mut expected = straight.clone();
+    premultiply_alpha(&mut expected);
+    assert_eq!(pixels(&premultiplied), pixels(&expected));
+    // The half transparent texel is occluded to 32 before being halved
+    assert_eq!(pixels(&premultiplied)[3], [16, 16, 16, 128]);
+}
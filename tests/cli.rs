@@ -0,0 +1,123 @@
+#![cfg(feature = "cli")]
+
+extern crate image;
+extern crate serde_json;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use serde_json::Value as JsonValue;
+
+// The exit statuses of the command line tool
+const EXIT_OK: i32 = 0;
+const EXIT_FATAL: i32 = 1;
+const EXIT_MATERIALS_FAILED: i32 = 2;
+
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// An empty directory for the output of one test.
+fn out_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs the command line tool on a fixture, writing into `out`.
+fn run(fixture: &str, out: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_gltf_unlit_generator"))
+        .arg(fixtures_dir().join(fixture))
+        .arg("--out").arg(out)
+        .arg("--quiet")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn pixels(path: &PathBuf) -> Vec<[u8; 4]> {
+    image::open(path).unwrap().to_rgba().pixels().map(|pixel| pixel.data).collect()
+}
+
+#[test]
+fn flattens_opaque_materials_as_straight_alpha() {
+    // Opaque materials are never premultiplied, so their alpha still weighs their colors
+    let out = out_dir("flattens_opaque_materials_as_straight_alpha");
+    let output = run("base_only.gltf", &out, &["--format", "png", "--premultiply", "--flatten", "--background", "0,0,0"]);
+    assert!(output.status.success());
+    assert_eq!(pixels(&out.join("base_only_unlit.png")), vec![
+        [200, 50, 50, 255],
+        [0, 0, 0, 255],
+        [255, 127, 255, 255],
+        [64, 32, 64, 255]
+    ]);
+}
+
+#[test]
+fn exits_with_the_status_of_the_run() {
+    let out = out_dir("exits_with_the_status_of_the_run");
+    let extra_occlusion = fixtures_dir().join("occlusion_4x4.png");
+    let extra_occlusion = extra_occlusion.to_str().unwrap();
+    let status = |args: &[&str]| run("base_occlusion.gltf", &out, args).status.code();
+    assert_eq!(status(&["--force"]), Some(EXIT_OK));
+    // The extra occlusion map is larger than the material, so it fails
+    assert_eq!(status(&["--force", "--extra-occlusion", extra_occlusion]), Some(EXIT_MATERIALS_FAILED));
+    assert_eq!(status(&["--force", "--extra-occlusion", extra_occlusion, "--strict"]), Some(EXIT_FATAL));
+    assert_eq!(status(&["--force", "--no-such-flag"]), Some(EXIT_FATAL));
+    assert_eq!(status(&["--force", "--ao-gamma", "0"]), Some(EXIT_FATAL));
+}
+
+#[test]
+fn fails_when_the_gltf_cannot_be_written() {
+    let out = out_dir("fails_when_the_gltf_cannot_be_written");
+    // Without --force the existing glTF is not overwritten
+    fs::write(out.join("base_only_unlit.gltf"), "{}").unwrap();
+    let output = run("base_only.gltf", &out, &["--write-gltf"]);
+    assert_eq!(output.status.code(), Some(EXIT_FATAL));
+    assert!(out.join("base_only_unlit.jpg").exists());
+}
+
+#[test]
+fn rejects_write_gltf_with_formats_gltf_cannot_load() {
+    let out = out_dir("rejects_write_gltf_with_formats_gltf_cannot_load");
+    let output = run("base_only.gltf", &out, &["--write-gltf", "--hdr"]);
+    assert_eq!(output.status.code(), Some(EXIT_FATAL));
+    assert!(!out.join("base_only_unlit.gltf").exists());
+}
+
+#[test]
+fn references_webp_textures_through_ext_texture_webp() {
+    let out = out_dir("references_webp_textures_through_ext_texture_webp");
+    let output = run("base_only.gltf", &out, &["--write-gltf", "--format", "webp"]);
+    assert!(output.status.success());
+    let document: JsonValue = serde_json::from_slice(&fs::read(out.join("base_only_unlit.gltf")).unwrap()).unwrap();
+    let texture = &document["textures"][document["materials"][0]["pbrMetallicRoughness"]["baseColorTexture"]["index"].as_u64().unwrap() as usize];
+    let image = texture["extensions"]["EXT_texture_webp"]["source"].as_u64().unwrap();
+    assert!(texture["source"].is_null());
+    assert_eq!(document["images"][image as usize]["uri"], "base_only_unlit.webp");
+    assert!(document["extensionsUsed"].as_array().unwrap().contains(&JsonValue::from("EXT_texture_webp")));
+    assert_eq!(document["extensionsRequired"], JsonValue::from(vec!["EXT_texture_webp"]));
+}
+
+#[test]
+fn rejects_write_gltf_with_raw_output() {
+    let out = out_dir("rejects_write_gltf_with_raw_output");
+    let output = run("base_only.gltf", &out, &["--write-gltf", "--format", "raw"]);
+    assert_eq!(output.status.code(), Some(EXIT_FATAL));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'--format raw' cannot be used with '--write-gltf'"));
+    assert!(!out.join("base_only_unlit.rgba").exists());
+    assert!(!out.join("base_only_unlit.gltf").exists());
+}
+
+#[test]
+fn overrides_conflicting_config_options_with_the_command_line() {
+    let out = out_dir("overrides_conflicting_config_options_with_the_command_line");
+    let config = out.join("unlit.toml");
+    fs::write(&config, "hdr = true\nquality = 80\n").unwrap();
+    let output = run("base_only.gltf", &out, &["--config", config.to_str().unwrap(), "--format", "png"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(out.join("base_only_unlit.png").exists());
+    assert!(!out.join("base_only_unlit.hdr").exists());
+}